@@ -4,43 +4,134 @@ use std::{path::PathBuf, str::FromStr};
 
 use clap::Parser;
 use lib::{
+    activation_order::ActivationOrder,
     array_solution::ArraySolution,
     distance::DistanceFunction,
-    divide_and_conqure_solver::{self, DivideAndConqureConfig},
+    divide_and_conqure_solver::{self, DivideAndConqureConfig, PartitionMode},
     euclid_distance::EuclidDistance,
     evaluate::evaluate,
+    held_karp::{self, HeldKarpConfig},
+    improvement_strategy::ImprovementStrategy,
     lkh::{self, LKHConfig},
+    matrix_distance::MatrixDistance,
     opt2::{self, Opt2Config},
     opt3::{self, Opt3Config},
+    symmetric_transform::SymmetricTransform,
 };
 
 #[derive(Parser)]
 struct Argument {
     problem_path: std::path::PathBuf,
+    // 使うスレッド数。0 なら全コア
+    #[arg(long, default_value_t = 0)]
+    no_thread: usize,
+    // 分割統治の分割数
+    #[arg(long, default_value_t = 12)]
+    no_split: u32,
+    // Held-Karp 下界を求め、評価値との差を表示する
+    #[arg(long)]
+    lower_bound: bool,
 }
 
 fn get_default_cache_filepath(distance: &impl DistanceFunction) -> String {
     format!("{}.cache", distance.name())
 }
 
+// 評価値と、下界があれば下界に対する差の割合を表示する
+fn report_eval(eval: i64, lower_bound: Option<i64>) {
+    match lower_bound.filter(|&lower_bound| lower_bound > 0) {
+        Some(lower_bound) => {
+            let gap = (eval - lower_bound) as f64 / lower_bound as f64 * 100.0;
+            eprintln!("eval = {} (gap = {:.3}%)", eval, gap);
+        }
+        None => eprintln!("eval = {}", eval),
+    }
+}
+
+fn is_atsp(problem_path: &PathBuf) -> bool {
+    std::fs::read_to_string(problem_path)
+        .unwrap()
+        .lines()
+        .any(|line| line.starts_with("TYPE") && line.contains("ATSP"))
+}
+
+// 非対称な問題は 2n 頂点の対称な問題に変換して LKH で解く
+fn solve_atsp(problem_path: &PathBuf) {
+    let distance = MatrixDistance::load_tsplib(problem_path);
+    let transform = SymmetricTransform::new(&distance);
+    let solution =
+        transform.to_symmetric_solution(&ArraySolution::new(distance.dimension() as usize));
+
+    let solution = lkh::solve(
+        &transform,
+        solution,
+        LKHConfig {
+            use_neighbor_cache: false,
+            cache_filepath: PathBuf::new(),
+            debug: false,
+            time_ms: 60_000,
+            start_kick_step: 30,
+            kick_step_diff: 10,
+            end_kick_step: transform.dimension() as usize / 10,
+            fail_count_threashold: 50,
+            max_depth: 6,
+            use_non_sequential_move: false,
+            improvement_strategy: ImprovementStrategy::BestImprovement,
+            activation_order: ActivationOrder::Random,
+        },
+    );
+    let solution = transform.to_asymmetric_solution(&solution);
+    eprintln!("finish lkh.");
+    eprintln!("eval = {}", evaluate(&distance, &solution));
+}
+
 fn main() {
     let args = Argument::parse();
+    if is_atsp(&args.problem_path) {
+        solve_atsp(&args.problem_path);
+        return;
+    }
     let distance = EuclidDistance::load_tsplib(&args.problem_path);
     let solution = ArraySolution::new(distance.dimension() as usize);
 
     let cache_filepath = get_default_cache_filepath(&distance);
+    let no_thread = if args.no_thread == 0 {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        args.no_thread
+    };
 
-    let solution = opt3::solve(
+    let (solution, _) = opt3::solve(
         &distance,
         solution,
         Opt3Config {
             use_neighbor_cache: true,
             cache_filepath: PathBuf::from_str(cache_filepath.as_str()).unwrap(),
             debug: false,
+            time_ms: Some(60_000),
+            max_iteration: None,
+            improvement_strategy: ImprovementStrategy::BestImprovement,
+            activation_order: ActivationOrder::Random,
+            or_opt_max_len: 3,
         },
     );
     eprintln!("finish 3-opt.");
-    eprintln!("eval = {}", evaluate(&distance, &solution));
+
+    let lower_bound = args.lower_bound.then(|| {
+        let lower_bound = held_karp::lower_bound(
+            &distance,
+            evaluate(&distance, &solution),
+            HeldKarpConfig {
+                neighbor_size: 8,
+                max_iteration: 300,
+                time_ms: 60_000,
+                debug: false,
+            },
+        );
+        eprintln!("lower bound = {}", lower_bound);
+        lower_bound
+    });
+    report_eval(evaluate(&distance, &solution), lower_bound);
 
     let mut solution = lkh::solve(
         &distance,
@@ -55,10 +146,13 @@ fn main() {
             end_kick_step: distance.dimension() as usize / 10,
             fail_count_threashold: 50,
             max_depth: 6,
+            use_non_sequential_move: false,
+            improvement_strategy: ImprovementStrategy::BestImprovement,
+            activation_order: ActivationOrder::Random,
         },
     );
     eprintln!("finish initial lkh.");
-    eprintln!("eval = {}", evaluate(&distance, &solution));
+    report_eval(evaluate(&distance, &solution), lower_bound);
 
     // 分割して並列化
 
@@ -71,7 +165,10 @@ fn main() {
             &distance,
             &solution,
             DivideAndConqureConfig {
-                no_split: 12,
+                no_split: args.no_split,
+                partition_mode: PartitionMode::TourSegment,
+                seam_window_size: 200,
+                no_thread,
                 debug: false,
                 time_ms,
                 start_kick_step,
@@ -79,11 +176,14 @@ fn main() {
                 end_kick_step: distance.dimension() as usize / 10,
                 fail_count_threashold: 50,
                 max_depth: 7,
+                use_non_sequential_move: false,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
             },
         );
         let eval = evaluate(&distance, &solution);
         eprintln!("finish splited lkh {} times.", iter);
-        eprintln!("eval = {}", eval);
+        report_eval(eval, lower_bound);
         if best_eval == eval {
             start_kick_step += 10;
             time_ms += 30_000;
@@ -108,8 +208,11 @@ fn main() {
             end_kick_step: distance.dimension() as usize / 10,
             fail_count_threashold: 50,
             max_depth: 7,
+            use_non_sequential_move: false,
+            improvement_strategy: ImprovementStrategy::BestImprovement,
+            activation_order: ActivationOrder::Random,
         },
     );
     eprintln!("finish initial lkh.");
-    eprintln!("eval = {}", evaluate(&distance, &solution));
+    report_eval(evaluate(&distance, &solution), lower_bound);
 }
@@ -0,0 +1,70 @@
+use rand::rngs::ThreadRng;
+
+use crate::intset::{IntQueue, IntSet};
+
+// don't look bits が立っていない都市をどの順番で処理するか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivationOrder {
+    // 活性な都市からランダムに選ぶ
+    Random,
+    // 活性化された順に処理する。空間的な局所性が保たれるのでキャッシュに乗りやすい
+    Queue,
+}
+
+// 局所探索で処理待ちの都市を管理する
+pub(crate) enum ActiveSet {
+    Random(IntSet),
+    Queue(IntQueue),
+}
+
+impl ActiveSet {
+    pub fn new(n: usize, order: ActivationOrder) -> ActiveSet {
+        match order {
+            ActivationOrder::Random => ActiveSet::Random(IntSet::new(n)),
+            ActivationOrder::Queue => ActiveSet::Queue(IntQueue::new(n)),
+        }
+    }
+
+    pub fn set_all(&mut self) {
+        match self {
+            ActiveSet::Random(set) => set.set_all(),
+            ActiveSet::Queue(queue) => queue.set_all(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            ActiveSet::Random(set) => set.is_empty(),
+            ActiveSet::Queue(queue) => queue.is_empty(),
+        }
+    }
+
+    pub fn push(&mut self, id: u32) {
+        match self {
+            ActiveSet::Random(set) => set.push(id),
+            ActiveSet::Queue(queue) => queue.push(id),
+        }
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        match self {
+            ActiveSet::Random(set) => set.remove(id),
+            ActiveSet::Queue(queue) => queue.remove(id),
+        }
+    }
+
+    // 次に処理する都市を選ぶ。選んだ都市は集合から取り除かない
+    pub fn select(&mut self, rng: &mut ThreadRng) -> u32 {
+        match self {
+            ActiveSet::Random(set) => set.random_select(rng),
+            ActiveSet::Queue(queue) => queue.front(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ActiveSet::Random(set) => set.len(),
+            ActiveSet::Queue(queue) => queue.len(),
+        }
+    }
+}
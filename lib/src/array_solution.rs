@@ -18,14 +18,18 @@ impl ArraySolution {
         self.content.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+
     pub fn from_array(content: Vec<u32>) -> ArraySolution {
-        let mut index_of: Vec<u32> = vec![std::u32::MAX; content.len()];
+        let mut index_of: Vec<u32> = vec![u32::MAX; content.len()];
         for (idx, id) in content.iter().enumerate() {
             index_of[*id as usize] = idx as u32;
         }
         // 全ての id が登録されている
-        for idx in 0..index_of.len() {
-            assert_ne!(index_of[idx], std::u32::MAX);
+        for index in index_of.iter() {
+            assert_ne!(*index, u32::MAX);
         }
 
         ArraySolution { content, index_of }
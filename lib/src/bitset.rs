@@ -7,10 +7,11 @@ impl BitSet {
     pub fn new(n: usize) -> BitSet {
         BitSet {
             valid_index: 0,
-            array: vec![std::u32::MAX; n],
+            array: vec![u32::MAX; n],
         }
     }
 
+    #[allow(dead_code)]
     pub fn len(&self) -> usize {
         self.array.len()
     }
@@ -0,0 +1,483 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::{
+    activation_order::ActivationOrder,
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    euclid_distance::{read_tsplib_header, read_tsplib_section, EuclidDistance},
+    improvement_strategy::ImprovementStrategy,
+    mtsp::{optimize_route, route_length},
+    neighbor_table::NeighborTable,
+    opt3::{self, Opt3Config},
+    solution::Solution,
+};
+
+// 経路内の最適化に 3-opt を使う最小の頂点数。これより短い経路は全ペアの 2-opt で十分
+const MIN_OPT3_ROUTE_SIZE: usize = 8;
+
+// 経路間の移動で、顧客の移動先の候補にする近傍の数
+const NEIGHBOR_SIZE: usize = 10;
+
+// 容量制約付き配送計画問題の、距離以外の情報
+pub struct Cvrp {
+    pub depot: u32,
+    pub capacity: i64,
+    // 頂点ごとの需要。デポは 0
+    pub demand_list: Vec<i64>,
+}
+
+impl Cvrp {
+    // 経路の積載量
+    pub fn load(&self, route: &[u32]) -> i64 {
+        route.iter().map(|&id| self.demand_list[id as usize]).sum()
+    }
+
+    // 全ての顧客をちょうど一度ずつ訪れ、各経路がデポから始まり容量を超えないか
+    pub fn validate(&self, route_list: &[Vec<u32>]) -> bool {
+        let mut visited = vec![false; self.demand_list.len()];
+        visited[self.depot as usize] = true;
+        for route in route_list {
+            if route.first() != Some(&self.depot) || self.load(route) > self.capacity {
+                return false;
+            }
+            for &id in &route[1..] {
+                if visited[id as usize] {
+                    return false;
+                }
+                visited[id as usize] = true;
+            }
+        }
+        visited.iter().all(|&v| v)
+    }
+}
+
+// TSPLIB / CVRPLIB 形式の問題を読む。頂点番号は 0 始まりに直す
+// 座標は EuclidDistance::load_tsplib で読み、容量と DEMAND_SECTION, DEPOT_SECTION を読み足す
+pub fn load_tsplib(filepath: &PathBuf) -> (EuclidDistance, Cvrp) {
+    let distance = EuclidDistance::load_tsplib(filepath);
+    let capacity = read_tsplib_header(filepath, "CAPACITY")
+        .expect("CAPACITY is required")
+        .parse()
+        .unwrap();
+    let mut demand_list = vec![0; distance.dimension() as usize];
+    let demand_row_list = read_tsplib_section(filepath, "DEMAND_SECTION");
+    assert_eq!(demand_row_list.len(), demand_list.len());
+    for row in demand_row_list {
+        demand_list[(row[0] - 1) as usize] = row[1];
+    }
+    let depot_list = read_tsplib_section(filepath, "DEPOT_SECTION")
+        .into_iter()
+        .flatten()
+        .take_while(|&id| id != -1)
+        .collect::<Vec<_>>();
+    assert_eq!(depot_list.len(), 1, "only a single depot is supported");
+
+    let depot = (depot_list[0] - 1) as u32;
+    demand_list[depot as usize] = 0;
+    (
+        distance,
+        Cvrp {
+            depot,
+            capacity,
+            demand_list,
+        },
+    )
+}
+
+// Clarke-Wright の savings 法
+// 顧客ごとに 1 つの経路から始め、d(0, i) + d(0, j) - d(i, j) の大きい順に、
+// 容量を超えない範囲で i と j が端にある経路同士を繋ぐ
+pub fn savings(distance: &impl DistanceFunction, cvrp: &Cvrp) -> Vec<Vec<u32>> {
+    let n = distance.dimension();
+    let depot = cvrp.depot;
+    let customer_list = (0..n).filter(|&id| id != depot).collect::<Vec<_>>();
+    for &id in &customer_list {
+        assert!(
+            cvrp.demand_list[id as usize] <= cvrp.capacity,
+            "demand exceeds capacity"
+        );
+    }
+
+    let mut saving_list = vec![];
+    for (index, &i) in customer_list.iter().enumerate() {
+        for &j in &customer_list[index + 1..] {
+            let saving =
+                distance.distance(depot, i) + distance.distance(depot, j) - distance.distance(i, j);
+            saving_list.push((saving, i, j));
+        }
+    }
+    saving_list.sort_by(|a, b| b.cmp(a));
+
+    // 経路は顧客だけを持ち、最後にデポを先頭に付ける
+    let mut route_list = (0..n)
+        .map(|id| if id == depot { None } else { Some(vec![id]) })
+        .collect::<Vec<_>>();
+    let mut route_id = (0..n as usize).collect::<Vec<_>>();
+    let mut load_list = cvrp.demand_list.clone();
+
+    for (saving, i, j) in saving_list {
+        if saving <= 0 {
+            break;
+        }
+        let (ri, rj) = (route_id[i as usize], route_id[j as usize]);
+        if ri == rj || load_list[ri] + load_list[rj] > cvrp.capacity {
+            continue;
+        }
+        let is_end = |route: &Vec<u32>, id: u32| route[0] == id || route[route.len() - 1] == id;
+        if !is_end(route_list[ri].as_ref().unwrap(), i)
+            || !is_end(route_list[rj].as_ref().unwrap(), j)
+        {
+            continue;
+        }
+
+        // i が末尾、j が先頭になる向きで繋ぐ
+        let mut route_i = route_list[ri].take().unwrap();
+        let mut route_j = route_list[rj].take().unwrap();
+        if route_i[route_i.len() - 1] != i {
+            route_i.reverse();
+        }
+        if route_j[0] != j {
+            route_j.reverse();
+        }
+        for &id in &route_j {
+            route_id[id as usize] = ri;
+        }
+        route_i.extend(route_j);
+        route_list[ri] = Some(route_i);
+        load_list[ri] += load_list[rj];
+    }
+
+    route_list
+        .into_iter()
+        .flatten()
+        .map(|route| {
+            let mut with_depot = vec![depot];
+            with_depot.extend(route);
+            with_depot
+        })
+        .collect()
+}
+
+// 経路の頂点だけからなる部分問題
+struct RouteDistance<'a, T: DistanceFunction> {
+    ref_distance: &'a T,
+    vertex_map: &'a [u32],
+}
+
+impl<'a, T: DistanceFunction> DistanceFunction for RouteDistance<'a, T> {
+    fn distance(&self, id1: u32, id2: u32) -> i64 {
+        self.ref_distance
+            .distance(self.vertex_map[id1 as usize], self.vertex_map[id2 as usize])
+    }
+
+    fn dimension(&self) -> u32 {
+        self.vertex_map.len() as u32
+    }
+
+    fn name(&self) -> String {
+        self.ref_distance.name()
+    }
+
+    fn is_symmetric(&self) -> bool {
+        self.ref_distance.is_symmetric()
+    }
+}
+
+// 経路内の最適化。長い経路は 3-opt (or-opt を含む)、短い経路は 2-opt で行う
+// 経路の先頭はデポのままにする
+fn optimize_route_by_opt3(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    route: &mut [u32],
+) {
+    if route.len() < MIN_OPT3_ROUTE_SIZE {
+        optimize_route(distance, route);
+        return;
+    }
+    let route_distance = RouteDistance {
+        ref_distance: distance,
+        vertex_map: route,
+    };
+    let (solution, _) = opt3::solve(
+        &route_distance,
+        ArraySolution::new(route.len()),
+        Opt3Config {
+            use_neighbor_cache: false,
+            cache_filepath: PathBuf::new(),
+            debug: false,
+            time_ms: None,
+            max_iteration: None,
+            improvement_strategy: ImprovementStrategy::BestImprovement,
+            activation_order: ActivationOrder::Random,
+            or_opt_max_len: 3,
+        },
+    );
+    // 部分問題の頂点 0 がデポ
+    let mut optimized = Vec::with_capacity(route.len());
+    let mut id = 0;
+    for _iter in 0..route.len() {
+        optimized.push(route[id as usize]);
+        id = solution.next(id);
+    }
+    route.copy_from_slice(&optimized);
+}
+
+// route[index] を取り除いたときの長さの減少量
+fn removal_gain(distance: &impl DistanceFunction, route: &[u32], index: usize) -> i64 {
+    let prev = route[index - 1];
+    let next = route[(index + 1) % route.len()];
+    distance.distance(prev, route[index]) + distance.distance(route[index], next)
+        - distance.distance(prev, next)
+}
+
+// route[index] を id に置き換えたときの長さの増加量
+fn replacement_cost(distance: &impl DistanceFunction, route: &[u32], index: usize, id: u32) -> i64 {
+    let prev = route[index - 1];
+    let next = route[(index + 1) % route.len()];
+    distance.distance(prev, id) + distance.distance(id, next)
+        - distance.distance(prev, route[index])
+        - distance.distance(route[index], next)
+}
+
+// 経路間の移動で最も良い改善を探して適用する。改善がなければ None
+// relocate: 顧客を別の経路の、近傍の頂点の隣へ移す
+// exchange: 顧客を別の経路の、近傍の頂点の隣の顧客と交換する
+fn apply_best_inter_route_move(
+    distance: &impl DistanceFunction,
+    neighbor_table: &NeighborTable,
+    cvrp: &Cvrp,
+    route_list: &mut [Vec<u32>],
+    load_list: &mut [i64],
+) -> Option<(usize, usize)> {
+    // 顧客ごとの (経路, 位置)。デポは全ての経路の先頭にあるので持たない
+    let mut position_list = vec![(usize::MAX, 0); cvrp.demand_list.len()];
+    for (r, route) in route_list.iter().enumerate() {
+        for (index, &id) in route.iter().enumerate().skip(1) {
+            position_list[id as usize] = (r, index);
+        }
+    }
+
+    // (ゲイン, 移動元の経路, 位置, 移動先の経路, 位置, 交換か)
+    let mut best: Option<(i64, usize, usize, usize, usize, bool)> = None;
+    // 近傍の頂点 c の隣 (経路 r2 の index2 と index2 + 1 の間) に入れる候補
+    let mut insertion_list = vec![];
+    for (r1, route1) in route_list.iter().enumerate() {
+        for index1 in 1..route1.len() {
+            let id1 = route1[index1];
+            let demand1 = cvrp.demand_list[id1 as usize];
+            let gain1 = removal_gain(distance, route1, index1);
+
+            insertion_list.clear();
+            for &c in neighbor_table.neighbor_list(id1) {
+                if c == cvrp.depot {
+                    for (r2, route2) in route_list.iter().enumerate() {
+                        insertion_list.push((r2, 0));
+                        insertion_list.push((r2, route2.len() - 1));
+                    }
+                } else {
+                    let (r2, index2) = position_list[c as usize];
+                    insertion_list.push((r2, index2 - 1));
+                    insertion_list.push((r2, index2));
+                }
+            }
+
+            for &(r2, index2) in insertion_list.iter() {
+                if r1 == r2 {
+                    continue;
+                }
+                let route2 = &route_list[r2];
+                let prev = route2[index2];
+                let next = route2[(index2 + 1) % route2.len()];
+                if load_list[r2] + demand1 <= cvrp.capacity {
+                    let gain = gain1 + distance.distance(prev, next)
+                        - distance.distance(prev, id1)
+                        - distance.distance(id1, next);
+                    if gain > best.map_or(0, |best| best.0) {
+                        best = Some((gain, r1, index1, r2, index2, false));
+                    }
+                }
+                // id1 を c の隣に置くように、c の隣の顧客と交換する
+                for index2 in [index2, (index2 + 1) % route2.len()] {
+                    if index2 == 0 {
+                        continue;
+                    }
+                    let id2 = route2[index2];
+                    let demand2 = cvrp.demand_list[id2 as usize];
+                    if load_list[r1] - demand1 + demand2 > cvrp.capacity
+                        || load_list[r2] - demand2 + demand1 > cvrp.capacity
+                    {
+                        continue;
+                    }
+                    let gain = -replacement_cost(distance, route1, index1, id2)
+                        - replacement_cost(distance, route2, index2, id1);
+                    if gain > best.map_or(0, |best| best.0) {
+                        best = Some((gain, r1, index1, r2, index2, true));
+                    }
+                }
+            }
+        }
+    }
+
+    let (_, r1, index1, r2, index2, exchange) = best?;
+    if exchange {
+        let (id1, id2) = (route_list[r1][index1], route_list[r2][index2]);
+        route_list[r1][index1] = id2;
+        route_list[r2][index2] = id1;
+        let diff = cvrp.demand_list[id2 as usize] - cvrp.demand_list[id1 as usize];
+        load_list[r1] += diff;
+        load_list[r2] -= diff;
+    } else {
+        let id1 = route_list[r1].remove(index1);
+        route_list[r2].insert(index2 + 1, id1);
+        load_list[r1] -= cvrp.demand_list[id1 as usize];
+        load_list[r2] += cvrp.demand_list[id1 as usize];
+    }
+    Some((r1, r2))
+}
+
+pub struct CvrpConfig {
+    pub debug: bool,
+    // None なら局所最適になるまで
+    pub time_ms: Option<u128>,
+}
+
+// 経路内の 3-opt と、経路間の relocate / exchange で改善する
+// 顧客がいなくなった経路は取り除く
+pub fn improve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    cvrp: &Cvrp,
+    route_list: &mut Vec<Vec<u32>>,
+    config: &CvrpConfig,
+) {
+    let start = Instant::now();
+    for route in route_list.iter_mut() {
+        optimize_route_by_opt3(distance, route);
+    }
+    let mut load_list = route_list
+        .iter()
+        .map(|route| cvrp.load(route))
+        .collect::<Vec<_>>();
+    let neighbor_table = NeighborTable::build(distance, NEIGHBOR_SIZE);
+
+    for iter in 0.. {
+        let Some((r1, r2)) = apply_best_inter_route_move(
+            distance,
+            &neighbor_table,
+            cvrp,
+            route_list,
+            &mut load_list,
+        ) else {
+            break;
+        };
+        optimize_route_by_opt3(distance, &mut route_list[r1]);
+        optimize_route_by_opt3(distance, &mut route_list[r2]);
+        if route_list[r1].len() == 1 {
+            route_list.swap_remove(r1);
+            load_list.swap_remove(r1);
+        }
+
+        if config.debug {
+            let eval: i64 = route_list
+                .iter()
+                .map(|route| route_length(distance, route))
+                .sum();
+            eprintln!(
+                "iter = {}, eval = {}, routes = {}",
+                iter,
+                eval,
+                route_list.len()
+            );
+        }
+        if config
+            .time_ms
+            .is_some_and(|time_ms| start.elapsed().as_millis() > time_ms)
+        {
+            break;
+        }
+    }
+    route_list.retain(|route| route.len() > 1);
+}
+
+// savings 法で経路を作り、局所探索で改善する
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    cvrp: &Cvrp,
+    config: CvrpConfig,
+) -> Vec<Vec<u32>> {
+    let mut route_list = savings(distance, cvrp);
+    improve(distance, cvrp, &mut route_list, &config);
+    route_list
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rand::{thread_rng, Rng};
+
+    use crate::mtsp::{evaluate_routes, MtspObjective};
+
+    use crate::test_util::temp_filepath;
+
+    use super::{improve, load_tsplib, savings, CvrpConfig};
+
+    #[test]
+    fn test_cvrp() {
+        const SIZE: usize = 80;
+        let mut rng = thread_rng();
+
+        let filepath = temp_filepath("cvrp_test.vrp");
+        let mut f = std::fs::File::create(&filepath).unwrap();
+        writeln!(
+            f,
+            "NAME : test\nTYPE : CVRP\nDIMENSION : {}\nEDGE_WEIGHT_TYPE : EUC_2D\nCAPACITY : 100",
+            SIZE
+        )
+        .unwrap();
+        writeln!(f, "NODE_COORD_SECTION").unwrap();
+        for id in 1..=SIZE {
+            writeln!(
+                f,
+                " {} {} {}",
+                id,
+                rng.gen_range(0..1000),
+                rng.gen_range(0..1000)
+            )
+            .unwrap();
+        }
+        writeln!(f, "DEMAND_SECTION").unwrap();
+        for id in 1..=SIZE {
+            writeln!(
+                f,
+                "{} {}",
+                id,
+                if id == 3 { 0 } else { rng.gen_range(1..30) }
+            )
+            .unwrap();
+        }
+        writeln!(f, "DEPOT_SECTION\n 3\n -1\nEOF").unwrap();
+        drop(f);
+
+        let (distance, cvrp) = load_tsplib(&filepath);
+        std::fs::remove_file(&filepath).unwrap();
+        assert_eq!(cvrp.depot, 2);
+        assert_eq!(cvrp.capacity, 100);
+        assert_eq!(cvrp.demand_list.len(), SIZE);
+
+        let mut route_list = savings(&distance, &cvrp);
+        assert!(cvrp.validate(&route_list));
+        let initial_eval = evaluate_routes(&distance, &route_list, MtspObjective::Total);
+
+        improve(
+            &distance,
+            &cvrp,
+            &mut route_list,
+            &CvrpConfig {
+                debug: false,
+                time_ms: None,
+            },
+        );
+        assert!(cvrp.validate(&route_list));
+        assert!(evaluate_routes(&distance, &route_list, MtspObjective::Total) <= initial_eval);
+    }
+}
@@ -6,4 +6,30 @@ pub trait DistanceFunction {
     fn dimension(&self) -> u32;
 
     fn name(&self) -> String;
+
+    // distance(id1, id2) == distance(id2, id1) が常に成り立つか
+    // 2-opt や LKH のように区間を反転する解法は対称な問題にしか使えない
+    fn is_symmetric(&self) -> bool {
+        true
+    }
+
+    // 巡回路に必ず含める辺か
+    fn is_fixed(&self, _id1: u32, _id2: u32) -> bool {
+        false
+    }
+
+    // 巡回路に含めてはいけない辺か
+    fn is_forbidden(&self, _id1: u32, _id2: u32) -> bool {
+        false
+    }
+
+    // 巡回路に必ず含める辺の一覧
+    fn fixed_edge_list(&self) -> Vec<(u32, u32)> {
+        vec![]
+    }
+
+    // 座標を持つ問題なら (y, x) を返す
+    fn coordinate(&self, _id: u32) -> Option<(i64, i64)> {
+        None
+    }
 }
@@ -1,36 +1,46 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use crate::{
+    activation_order::ActivationOrder,
     array_solution::ArraySolution,
     distance::DistanceFunction,
-    divide_and_conqure_solver,
+    edge_constraint::FIXED_EDGE_COST,
+    gpx,
+    improvement_strategy::ImprovementStrategy,
     lkh::{self, LKHConfig},
+    neighbor_table::NeighborTable,
     solution::Solution,
 };
 use rand::{thread_rng, Rng};
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rayon::{
+    prelude::{IntoParallelIterator, ParallelIterator},
+    ThreadPool, ThreadPoolBuilder,
+};
+
+// これより小さい部分問題は最適化しない
+const MIN_PART_SIZE: usize = 8;
 
-struct DividedDistance<'a, T: DistanceFunction> {
+// 元の問題の頂点 vertex_map[i] を i と番号付け直した部分問題
+// 外側の経路を縮約した辺と元の問題の固定辺を、固定辺として報告する
+pub struct DividedDistance<'a, T: DistanceFunction> {
     ref_distance: &'a T,
     vertex_map: Vec<u32>,
-    begin: u32,
-    end: u32,
+    // 各頂点と固定辺で繋がる頂点
+    fixed_list: Vec<Vec<u32>>,
     name: String,
 }
 
 impl<'a, T: DistanceFunction> DividedDistance<'a, T> {
-    fn new(
+    pub fn new(
         ref_distance: &'a T,
         vertex_map: Vec<u32>,
-        begin: u32,
-        end: u32,
+        fixed_list: Vec<Vec<u32>>,
         name: String,
     ) -> DividedDistance<'a, T> {
         DividedDistance {
             ref_distance,
             vertex_map,
-            begin,
-            end,
+            fixed_list,
             name,
         }
     }
@@ -38,9 +48,9 @@ impl<'a, T: DistanceFunction> DividedDistance<'a, T> {
 
 impl<'a, T: DistanceFunction> DistanceFunction for DividedDistance<'a, T> {
     fn distance(&self, id1: u32, id2: u32) -> i64 {
-        // 巡回路じゃなくて start -> end までのパスを求めたいので、ここを短絡させて経路を作る
-        if (id1 == self.begin && id2 == self.end) || (id1 == self.end && id2 == self.begin) {
-            0
+        // 巡回路じゃなくて、部分問題に含まれるパスを求めたいので、パスの端点を繋ぐ辺を固定して経路を作る
+        if self.fixed_list[id1 as usize].contains(&id2) {
+            FIXED_EDGE_COST
         } else {
             let orig_id1 = self.vertex_map[id1 as usize];
             let orig_id2 = self.vertex_map[id2 as usize];
@@ -55,80 +65,762 @@ impl<'a, T: DistanceFunction> DistanceFunction for DividedDistance<'a, T> {
     fn name(&self) -> String {
         self.name.clone()
     }
+
+    fn is_fixed(&self, id1: u32, id2: u32) -> bool {
+        self.fixed_list[id1 as usize].contains(&id2)
+            || self
+                .ref_distance
+                .is_fixed(self.vertex_map[id1 as usize], self.vertex_map[id2 as usize])
+    }
+
+    fn is_forbidden(&self, id1: u32, id2: u32) -> bool {
+        self.ref_distance
+            .is_forbidden(self.vertex_map[id1 as usize], self.vertex_map[id2 as usize])
+    }
+
+    fn fixed_edge_list(&self) -> Vec<(u32, u32)> {
+        let local_id = self
+            .vertex_map
+            .iter()
+            .enumerate()
+            .map(|(id, &orig_id)| (orig_id, id as u32))
+            .collect::<HashMap<_, _>>();
+        let mut edge_list = self
+            .fixed_list
+            .iter()
+            .enumerate()
+            .flat_map(|(id1, list)| list.iter().map(move |&id2| (id1 as u32, id2)))
+            .filter(|&(id1, id2)| id1 < id2)
+            .chain(
+                self.ref_distance
+                    .fixed_edge_list()
+                    .into_iter()
+                    .filter_map(|(id1, id2)| Some((*local_id.get(&id1)?, *local_id.get(&id2)?)))
+                    .map(|(id1, id2)| (id1.min(id2), id1.max(id2))),
+            )
+            .collect::<Vec<_>>();
+        edge_list.sort();
+        edge_list.dedup();
+        edge_list
+    }
+
+    fn coordinate(&self, id: u32) -> Option<(i64, i64)> {
+        self.ref_distance.coordinate(self.vertex_map[id as usize])
+    }
+}
+
+// 問題の分割方法
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionMode {
+    // 現在の巡回路を、ランダムな都市から始まる連続した区間に分割する
+    TourSegment,
+    // 座標の広い方の軸で再帰的に二等分する (Karp の分割)
+    // 座標を持たない問題では TourSegment と同じ
+    Geometric,
 }
 
 pub struct DivideAndConqureConfig {
     pub no_split: u32,
+    pub partition_mode: PartitionMode,
+    // 並列に解いた後、分割の境目をまたぐこの頂点数の区間を解き直す。0 なら行わない
+    pub seam_window_size: usize,
+    // 使うスレッド数。0 なら rayon の既定値
+    // no_split はスレッド数と独立に決めてよく、スレッド数より多く分割すると空いたスレッドが残りの部分問題を取っていく
+    pub no_thread: usize,
     pub debug: bool,
+    // 全スレッドで部分問題を解き終えるまでの目安時間
+    // 各部分問題には頂点数に比例した時間を割り当てる
     pub time_ms: u128,
     pub start_kick_step: usize,
     pub kick_step_diff: usize,
     pub end_kick_step: usize,
     pub fail_count_threashold: u32,
     pub max_depth: usize,
+    pub use_non_sequential_move: bool,
+    pub improvement_strategy: ImprovementStrategy,
+    pub activation_order: ActivationOrder,
 }
 
-// スレッド数で問題を分割して、最終的に統合
-pub fn solve(
-    distance: &(impl DistanceFunction + std::marker::Sync),
+fn split_tour_segment(
+    distance: &impl DistanceFunction,
     solution: &impl Solution,
-    config: DivideAndConqureConfig,
-) -> ArraySolution {
+    no_split: u32,
+) -> Vec<Vec<u32>> {
     let mut rng = thread_rng();
     let mut id = rng.gen_range(0..distance.dimension());
-    let mut vertex_list = vec![vec![]; config.no_split as usize];
-    // 振り分け
-    for no_segment in 0..config.no_split {
-        let segment_len = distance.dimension() * (no_segment + 1) / config.no_split
-            - distance.dimension() * no_segment / config.no_split;
+    let mut vertex_list = vec![vec![]; no_split as usize];
+    for no_segment in 0..no_split {
+        let segment_len = distance.dimension() * (no_segment + 1) / no_split
+            - distance.dimension() * no_segment / no_split;
         for _iter in 0..segment_len {
             vertex_list[no_segment as usize].push(id);
             id = solution.next(id);
         }
     }
+    vertex_list
+}
 
-    // 分割統治の最適化
-    let new_vertex_list = vertex_list.into_par_iter().map(|vertex_list| -> Vec<u32> {
-        // 距離関数の生成
+fn split_geometric(distance: &impl DistanceFunction, no_split: u32) -> Option<Vec<Vec<u32>>> {
+    let coordinate_list = (0..distance.dimension())
+        .map(|id| distance.coordinate(id))
+        .collect::<Option<Vec<_>>>()?;
+
+    fn bisect(
+        coordinate_list: &[(i64, i64)],
+        mut id_list: Vec<u32>,
+        no_part: usize,
+        vertex_list: &mut Vec<Vec<u32>>,
+    ) {
+        if no_part == 1 {
+            vertex_list.push(id_list);
+            return;
+        }
+        let range = |axis: fn(&(i64, i64)) -> i64| {
+            let value_list = id_list
+                .iter()
+                .map(|&id| axis(&coordinate_list[id as usize]));
+            value_list.clone().max().unwrap() - value_list.min().unwrap()
+        };
+        let axis: fn(&(i64, i64)) -> i64 = if range(|p| p.0) >= range(|p| p.1) {
+            |p| p.0
+        } else {
+            |p| p.1
+        };
+        id_list.sort_by_key(|&id| axis(&coordinate_list[id as usize]));
+
+        // 分割数に比例した頂点数で分ける
+        let left_part = no_part / 2;
+        let right_list = id_list.split_off(id_list.len() * left_part / no_part);
+        bisect(coordinate_list, id_list, left_part, vertex_list);
+        bisect(
+            coordinate_list,
+            right_list,
+            no_part - left_part,
+            vertex_list,
+        );
+    }
+
+    let mut vertex_list = vec![];
+    bisect(
+        &coordinate_list,
+        (0..distance.dimension()).collect(),
+        no_split as usize,
+        &mut vertex_list,
+    );
+    Some(vertex_list)
+}
+
+// 部分問題の頂点を、元の巡回路上で連続するパスに分ける
+// パスは巡回路の順に並び、各パスも巡回路の向きに沿う
+fn split_into_path(
+    solution: &impl Solution,
+    part_id: &[u32],
+    vertex_list: &[u32],
+) -> Vec<Vec<u32>> {
+    let part = part_id[vertex_list[0] as usize];
+    let mut start_list = vertex_list
+        .iter()
+        .cloned()
+        .filter(|&id| part_id[solution.prev(id) as usize] != part)
+        .collect::<Vec<_>>();
+    if start_list.is_empty() {
+        // 巡回路全体が 1 つの部分問題
+        start_list.push(vertex_list[0]);
+    }
+    start_list.sort_by_key(|&id| solution.index_of(id));
+
+    start_list
+        .into_iter()
+        .map(|start| {
+            let mut path = vec![start];
+            let mut id = solution.next(start);
+            while id != start && part_id[id as usize] == part {
+                path.push(id);
+                id = solution.next(id);
+            }
+            path
+        })
+        .collect()
+}
+
+// 頂点と、その新しい隣接頂点の組
+type AdjacentList = Vec<(u32, [u32; 2])>;
+
+// 部分問題。親の問題の頂点 vertex_map[i] を i と番号付け直し、外側の経路をパスの端点同士を繋ぐ固定辺に縮約したもの
+// 初期解は 0, 1, ..., n - 1 の順に辿る巡回路
+struct Part {
+    vertex_map: Vec<u32>,
+    fixed_list: Vec<Vec<u32>>,
+    // fixed_list と同じ並びで、縮約した固定辺なら親の問題で外へ出ていく先の頂点
+    // 親の問題から引き継いだ固定辺なら None
+    outside_list: Vec<Vec<Option<u32>>>,
+    no_fixed: usize,
+}
+
+impl Part {
+    // parent_fixed_list は親の問題の固定辺。部分問題の内側にあるものはそのまま固定する
+    fn new(
+        solution: &impl Solution,
+        path_list: Vec<Vec<u32>>,
+        parent_fixed_list: Option<&[Vec<u32>]>,
+    ) -> Part {
         // この順に元の解は [0, 1, 2, ..., ] という番号を付けるので、保存しておく
-        let vertex_map = vertex_list.clone();
-        let n = vertex_map.len() as u32 - 1;
-        let partial_distance = DividedDistance::new(distance, vertex_list, 0, n, "".to_string());
+        let vertex_map = path_list.iter().flatten().cloned().collect::<Vec<_>>();
+        let n = vertex_map.len();
+
+        let mut fixed_list = vec![vec![]; n];
+        let mut outside_list = vec![vec![]; n];
+        let mut no_fixed = 0;
+        let mut add_fixed = |id1: u32, id2: u32, outside1: Option<u32>, outside2: Option<u32>| {
+            fixed_list[id1 as usize].push(id2);
+            fixed_list[id2 as usize].push(id1);
+            outside_list[id1 as usize].push(outside1);
+            outside_list[id2 as usize].push(outside2);
+            no_fixed += 1;
+        };
+
+        // パス i の終点とパス i + 1 の始点を固定辺で繋ぐ
+        // 固定辺は、元の巡回路では部分問題の外へ出ていく辺に対応する
+        let mut offset = 0;
+        let endpoint_list = path_list
+            .iter()
+            .map(|path| {
+                let endpoint = (offset as u32, (offset + path.len() - 1) as u32);
+                offset += path.len();
+                endpoint
+            })
+            .collect::<Vec<_>>();
+        for i in 0..endpoint_list.len() {
+            let (_, end) = endpoint_list[i];
+            let (start, _) = endpoint_list[(i + 1) % endpoint_list.len()];
+            add_fixed(
+                end,
+                start,
+                Some(solution.next(vertex_map[end as usize])),
+                Some(solution.prev(vertex_map[start as usize])),
+            );
+        }
+
+        if let Some(parent_fixed_list) = parent_fixed_list {
+            for &(start, end) in endpoint_list.iter() {
+                for id in start..end {
+                    let parent_id = vertex_map[id as usize];
+                    let parent_next = vertex_map[id as usize + 1];
+                    if parent_fixed_list[parent_id as usize].contains(&parent_next) {
+                        add_fixed(id, id + 1, None, None);
+                    }
+                }
+            }
+        }
+
+        Part {
+            vertex_map,
+            fixed_list,
+            outside_list,
+            no_fixed,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.vertex_map.len()
+    }
+
+    // 部分問題の解を、親の問題の頂点番号での隣接頂点に戻す
+    // 縮約した固定辺は元の巡回路で外へ出ていく辺に戻す
+    fn restore(&self, part_solution: &impl Solution) -> Option<AdjacentList> {
+        let mut adjacent_list = Vec::with_capacity(self.len());
+        let mut no_used_fixed = 0;
+        for id in 0..self.len() as u32 {
+            let mut adjacent = [0; 2];
+            for (slot, next) in [part_solution.prev(id), part_solution.next(id)]
+                .into_iter()
+                .enumerate()
+            {
+                let fixed = &self.fixed_list[id as usize];
+                adjacent[slot] = match fixed.iter().position(|&v| v == next) {
+                    Some(index) => {
+                        no_used_fixed += 1;
+                        self.outside_list[id as usize][index]
+                            .unwrap_or(self.vertex_map[next as usize])
+                    }
+                    None => self.vertex_map[next as usize],
+                };
+            }
+            adjacent_list.push((self.vertex_map[id as usize], adjacent));
+        }
+        // 固定辺を落とした解は元の巡回路に戻せないので捨てる
+        if no_used_fixed != 2 * self.no_fixed {
+            return None;
+        }
+        Some(adjacent_list)
+    }
+}
+
+// 全体で total_size 頂点の問題のうち part_size 頂点の部分問題に割り当てる時間
+// 部分問題の頂点数の合計が total_size なら、全スレッドで解き終えるのにおよそ time_ms かかる
+fn part_time_ms(config: &DivideAndConqureConfig, part_size: usize, total_size: usize) -> u128 {
+    let no_thread = rayon::current_num_threads() as u128;
+    (config.time_ms * no_thread * part_size as u128 / total_size.max(1) as u128).max(1)
+}
+
+fn to_lkh_config(config: &DivideAndConqureConfig, time_ms: u128) -> LKHConfig {
+    LKHConfig {
+        use_neighbor_cache: false,
+        cache_filepath: PathBuf::new(),
+        debug: config.debug,
+        time_ms,
+        start_kick_step: config.start_kick_step,
+        kick_step_diff: config.kick_step_diff,
+        end_kick_step: config.end_kick_step,
+        fail_count_threashold: config.fail_count_threashold,
+        max_depth: config.max_depth,
+        use_non_sequential_move: config.use_non_sequential_move,
+        improvement_strategy: config.improvement_strategy,
+        activation_order: config.activation_order,
+    }
+}
+
+// 隣接リストが 1 つの巡回路になっていれば、それを辿った解を返す
+fn to_solution(adjacent: &[[u32; 2]]) -> Option<ArraySolution> {
+    let mut vertex_array = Vec::with_capacity(adjacent.len());
+    let mut prev = adjacent[0][0];
+    let mut id = 0;
+    loop {
+        vertex_array.push(id);
+        let [v1, v2] = adjacent[id as usize];
+        let next = if v1 == prev { v2 } else { v1 };
+        prev = id;
+        id = next;
+        if id == 0 || vertex_array.len() == adjacent.len() {
+            break;
+        }
+    }
+    if id == 0 && vertex_array.len() == adjacent.len() {
+        Some(ArraySolution::from_array(vertex_array))
+    } else {
+        None
+    }
+}
+
+// 振り分け
+fn split(
+    distance: &impl DistanceFunction,
+    solution: &impl Solution,
+    parent_fixed_list: Option<&[Vec<u32>]>,
+    config: &DivideAndConqureConfig,
+) -> (Vec<Part>, Vec<u32>) {
+    let n = distance.dimension() as usize;
+    let vertex_list = match config.partition_mode {
+        PartitionMode::TourSegment => None,
+        PartitionMode::Geometric => split_geometric(distance, config.no_split),
+    }
+    .unwrap_or_else(|| split_tour_segment(distance, solution, config.no_split));
+
+    let mut part_id = vec![0; n];
+    for (part, vertex_list) in vertex_list.iter().enumerate() {
+        for &id in vertex_list.iter() {
+            part_id[id as usize] = part as u32;
+        }
+    }
+    let part_list = vertex_list
+        .iter()
+        .filter(|vertex_list| !vertex_list.is_empty())
+        .map(|vertex_list| split_into_path(solution, &part_id, vertex_list))
+        .map(|path_list| Part::new(solution, path_list, parent_fixed_list))
+        .collect();
+    (part_list, part_id)
+}
+
+// 分割の境目 (両端が別の部分問題に属する辺) を中心とした、巡回路上で連続する区間を作る
+// 区間同士は重ならない
+fn split_seam(solution: &impl Solution, part_id: &[u32], window_size: usize) -> Vec<Vec<u32>> {
+    let n = solution.len();
+    let window_size = window_size.min(n);
+    let Some(seam) =
+        (0..n as u32).find(|&id| part_id[id as usize] != part_id[solution.next(id) as usize])
+    else {
+        return vec![];
+    };
+
+    // 最初の境目が区間の中心に来るように、そこから半区間戻った頂点を先頭として巡回路を並べる
+    let mut start = seam;
+    for _iter in 1..window_size / 2 {
+        start = solution.prev(start);
+    }
+    let mut tour = Vec::with_capacity(n);
+    let mut id = start;
+    for _iter in 0..n {
+        tour.push(id);
+        id = solution.next(id);
+    }
 
-        let init_solution = ArraySolution::new(partial_distance.dimension() as usize);
-        let solution = lkh::solve(
+    let mut window_list = vec![];
+    let mut covered = 0;
+    for index in 0..n - 1 {
+        if index + 1 < covered || part_id[tour[index] as usize] == part_id[tour[index + 1] as usize]
+        {
+            continue;
+        }
+        let begin = (index + 1).saturating_sub(window_size / 2).max(covered);
+        let end = (begin + window_size).min(n);
+        window_list.push(tour[begin..end].to_vec());
+        covered = end;
+    }
+    window_list
+}
+
+// 境目をまたぐ区間を 1 本のパスからなる部分問題として並列に解き、統合する
+fn optimize_seam(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    solution: &ArraySolution,
+    part_id: &[u32],
+    parent_fixed_list: Option<&[Vec<u32>]>,
+    total_size: usize,
+    config: &DivideAndConqureConfig,
+) -> ArraySolution {
+    if config.seam_window_size < MIN_PART_SIZE {
+        return solution.clone();
+    }
+    let result_list = split_seam(solution, part_id, config.seam_window_size)
+        .into_par_iter()
+        .map(|window| {
+            let part = Part::new(solution, vec![window], parent_fixed_list);
+            if part.len() < MIN_PART_SIZE {
+                return None;
+            }
+            let partial_distance = DividedDistance::new(
+                distance,
+                part.vertex_map.clone(),
+                part.fixed_list.clone(),
+                "".to_string(),
+            );
+            let init_solution = ArraySolution::new(part.len());
+            let time_ms = part_time_ms(config, part.len(), total_size);
+            let part_solution = lkh::solve(
+                &partial_distance,
+                init_solution,
+                to_lkh_config(config, time_ms),
+            );
+            part.restore(&part_solution)
+        })
+        .collect::<Vec<_>>();
+    merge(distance, solution, result_list)
+}
+
+// 各部分問題の解は元の巡回路に対しては正しく繋がるが、
+// 他の部分問題の変更と組み合わせるとパスの繋がり方が変わって巡回路が分かれることがある
+// その場合は、元の巡回路にその部分問題だけを適用した解と GPX で統合する
+fn merge(
+    distance: &impl DistanceFunction,
+    solution: &impl Solution,
+    result_list: Vec<Option<AdjacentList>>,
+) -> ArraySolution {
+    let n = solution.len();
+    let original = (0..n as u32)
+        .map(|id| [solution.prev(id), solution.next(id)])
+        .collect::<Vec<_>>();
+    let mut adjacent = original.clone();
+    for adjacent_list in result_list.into_iter().flatten() {
+        let backup = adjacent_list
+            .iter()
+            .map(|&(id, _)| (id, adjacent[id as usize]))
+            .collect::<Vec<_>>();
+        for &(id, new_adjacent) in adjacent_list.iter() {
+            adjacent[id as usize] = new_adjacent;
+        }
+        if to_solution(&adjacent).is_some() {
+            continue;
+        }
+
+        for (id, old_adjacent) in backup {
+            adjacent[id as usize] = old_adjacent;
+        }
+        let mut part_adjacent = original.clone();
+        for (id, new_adjacent) in adjacent_list.into_iter() {
+            part_adjacent[id as usize] = new_adjacent;
+        }
+        if let Some(part_solution) = to_solution(&part_adjacent) {
+            let current = to_solution(&adjacent).unwrap();
+            let child = gpx::crossover(distance, &current, &part_solution);
+            for id in 0..n as u32 {
+                adjacent[id as usize] = [child.prev(id), child.next(id)];
+            }
+        }
+    }
+    to_solution(&adjacent).unwrap()
+}
+
+// 問題を分割して並列に解き、最終的に統合
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    solution: &(impl Solution + std::marker::Sync),
+    config: DivideAndConqureConfig,
+) -> ArraySolution {
+    build_thread_pool(config.no_thread).install(|| solve_impl(distance, solution, config))
+}
+
+fn build_thread_pool(no_thread: usize) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(no_thread)
+        .build()
+        .unwrap()
+}
+
+fn solve_impl(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    solution: &(impl Solution + std::marker::Sync),
+    config: DivideAndConqureConfig,
+) -> ArraySolution {
+    let n = distance.dimension() as usize;
+    let (part_list, part_id) = split(distance, solution, None, &config);
+
+    // 分割統治の最適化
+    let result_list = part_list
+        .into_par_iter()
+        .map(|part| {
+            if part.len() < MIN_PART_SIZE {
+                return None;
+            }
+            let partial_distance = DividedDistance::new(
+                distance,
+                part.vertex_map.clone(),
+                part.fixed_list.clone(),
+                "".to_string(),
+            );
+            let init_solution = ArraySolution::new(part.len());
+            let time_ms = part_time_ms(&config, part.len(), n);
+            let part_solution = lkh::solve(
+                &partial_distance,
+                init_solution,
+                to_lkh_config(&config, time_ms),
+            );
+            part.restore(&part_solution)
+        })
+        .collect::<Vec<_>>();
+
+    let solution = merge(distance, solution, result_list);
+    optimize_seam(distance, &solution, &part_id, None, n, &config)
+}
+
+// 部分問題の頂点数が leaf_size 以下になるまで再帰的に分割して解く
+// 近傍リストは全体で一度だけ作り、部分問題には親の近傍リストを絞り込んで渡す
+pub fn solve_recursive(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    solution: &(impl Solution + std::marker::Sync),
+    leaf_size: usize,
+    config: DivideAndConqureConfig,
+) -> ArraySolution {
+    build_thread_pool(config.no_thread)
+        .install(|| solve_recursive_impl(distance, solution, leaf_size, config))
+}
+
+fn solve_recursive_impl(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    solution: &(impl Solution + std::marker::Sync),
+    leaf_size: usize,
+    config: DivideAndConqureConfig,
+) -> ArraySolution {
+    let n = distance.dimension() as usize;
+
+    let neighbor_table = NeighborTable::build(distance, 5);
+
+    // 全体の問題も、現在の巡回路の順に番号を付け直した部分問題として扱う
+    let mut vertex_map = Vec::with_capacity(n);
+    let mut id = 0;
+    for _iter in 0..n {
+        vertex_map.push(id);
+        id = solution.next(id);
+    }
+    let neighbor_table = neighbor_table.restrict(&vertex_map);
+    let fixed_list = vec![vec![]; n];
+
+    let local_solution = solve_level(
+        distance,
+        &vertex_map,
+        &fixed_list,
+        &neighbor_table,
+        leaf_size.max(MIN_PART_SIZE),
+        n,
+        &config,
+    );
+    let mut vertex_array = Vec::with_capacity(n);
+    let mut id = 0;
+    for _iter in 0..n {
+        vertex_array.push(vertex_map[id as usize]);
+        id = local_solution.next(id);
+    }
+    ArraySolution::from_array(vertex_array)
+}
+
+// vertex_map は全体の問題での頂点番号。初期解は 0, 1, ..., n - 1 の順に辿る巡回路
+fn solve_level<T: DistanceFunction + std::marker::Sync>(
+    distance: &T,
+    vertex_map: &[u32],
+    fixed_list: &[Vec<u32>],
+    neighbor_table: &NeighborTable,
+    leaf_size: usize,
+    total_size: usize,
+    config: &DivideAndConqureConfig,
+) -> ArraySolution {
+    let n = vertex_map.len();
+    let partial_distance = DividedDistance::new(
+        distance,
+        vertex_map.to_vec(),
+        fixed_list.to_vec(),
+        "".to_string(),
+    );
+    let solution = ArraySolution::new(n);
+
+    if n <= leaf_size {
+        return lkh::solve_with_neighbor_table(
             &partial_distance,
-            init_solution,
-            LKHConfig {
-                use_neighbor_cache: false,
-                cache_filepath: PathBuf::new(),
-                debug: config.debug,
-                time_ms: config.time_ms,
-                start_kick_step: config.start_kick_step,
-                kick_step_diff: config.kick_step_diff,
-                end_kick_step: config.end_kick_step,
-                fail_count_threashold: config.fail_count_threashold,
-                max_depth: config.max_depth,
-            },
+            neighbor_table,
+            solution,
+            to_lkh_config(config, part_time_ms(config, n, total_size)),
         );
+    }
+
+    let (part_list, part_id) = split(&partial_distance, &solution, Some(fixed_list), config);
 
-        // 分割の復元
-        // solution 表記で 0 -> ... -> n の path を作りたいので、適切に方向を見て flip
-        let in_order = solution.prev(0) == n;
-
-        let mut vertex_array = vec![];
-        let mut id = 0;
-        for _iter in 0..solution.len() {
-            let orig_id = vertex_map[id as usize];
-            vertex_array.push(orig_id);
-            id = if in_order {
-                solution.next(id)
-            } else {
-                solution.prev(id)
+    let result_list = part_list
+        .into_par_iter()
+        .map(|part| {
+            if part.len() < MIN_PART_SIZE {
+                return None;
+            }
+            let child_vertex_map = part
+                .vertex_map
+                .iter()
+                .map(|&id| vertex_map[id as usize])
+                .collect::<Vec<_>>();
+            let child_neighbor_table = neighbor_table.restrict(&part.vertex_map);
+            let part_solution = solve_level(
+                distance,
+                &child_vertex_map,
+                &part.fixed_list,
+                &child_neighbor_table,
+                leaf_size,
+                total_size,
+                config,
+            );
+            part.restore(&part_solution)
+        })
+        .collect::<Vec<_>>();
+
+    let solution = merge(&partial_distance, &solution, result_list);
+    optimize_seam(
+        &partial_distance,
+        &solution,
+        &part_id,
+        Some(fixed_list),
+        total_size,
+        config,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        activation_order::ActivationOrder,
+        array_solution::ArraySolution,
+        distance::DistanceFunction,
+        edge_constraint::ConstrainedDistance,
+        euclid_distance::EuclidDistance,
+        evaluate::{evaluate, validate},
+        improvement_strategy::ImprovementStrategy,
+        test_util::random_euclid_distance,
+    };
+
+    use super::{
+        solve, solve_recursive, split_seam, DivideAndConqureConfig, DividedDistance, PartitionMode,
+    };
+
+    #[test]
+    fn test_divided_distance_fixed_edge() {
+        let coordinate_list = (0..8).map(|i| (i, i * i)).collect::<Vec<_>>();
+        let distance = EuclidDistance::new(&coordinate_list, "line".to_string());
+        let constrained = ConstrainedDistance::new(&distance, &[(6, 7), (1, 2)], &[]);
+
+        // 元の頂点 [2, 6, 7, 3] を 0, 1, 2, 3 とし、3 と 0 を縮約した辺で繋ぐ
+        let divided = DividedDistance::new(
+            &constrained,
+            vec![2, 6, 7, 3],
+            vec![vec![3], vec![], vec![], vec![0]],
+            "".to_string(),
+        );
+        assert!(divided.is_fixed(0, 3));
+        assert!(divided.is_fixed(2, 1));
+        assert!(!divided.is_fixed(0, 1));
+        assert_eq!(divided.fixed_edge_list(), vec![(0, 3), (1, 2)]);
+    }
+
+    #[test]
+    fn test_split_seam() {
+        let solution = ArraySolution::new(12);
+        let part_id = [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 0, 0];
+        let mut window_list = split_seam(&solution, &part_id, 4);
+        window_list.sort();
+        assert_eq!(window_list, vec![vec![2, 3, 4, 5], vec![8, 9, 10, 11]]);
+    }
+
+    #[test]
+    fn test_divide_and_conqure() {
+        const SIZE: usize = 400;
+
+        let distance = random_euclid_distance(SIZE);
+
+        for partition_mode in [PartitionMode::TourSegment, PartitionMode::Geometric] {
+            let initial = ArraySolution::new(SIZE);
+            let config = DivideAndConqureConfig {
+                no_split: 4,
+                partition_mode,
+                seam_window_size: 50,
+                no_thread: 2,
+                debug: false,
+                time_ms: 100,
+                start_kick_step: 5,
+                kick_step_diff: 5,
+                end_kick_step: 10,
+                fail_count_threashold: 10,
+                max_depth: 5,
+                use_non_sequential_move: true,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
             };
+            let solution = solve(&distance, &initial, config);
+
+            assert!(validate(&distance, &solution));
+            assert!(evaluate(&distance, &solution) < evaluate(&distance, &initial));
         }
-        vertex_array
-    });
+    }
+
+    #[test]
+    fn test_solve_recursive() {
+        const SIZE: usize = 1000;
+        let distance = random_euclid_distance(SIZE);
 
-    ArraySolution::from_array(new_vertex_list.flatten().collect::<Vec<_>>())
+        for partition_mode in [PartitionMode::TourSegment, PartitionMode::Geometric] {
+            let initial = ArraySolution::new(SIZE);
+            let config = DivideAndConqureConfig {
+                no_split: 4,
+                partition_mode,
+                seam_window_size: 50,
+                no_thread: 2,
+                debug: false,
+                time_ms: 50,
+                start_kick_step: 5,
+                kick_step_diff: 5,
+                end_kick_step: 10,
+                fail_count_threashold: 10,
+                max_depth: 5,
+                use_non_sequential_move: true,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+            };
+            let solution = solve_recursive(&distance, &initial, 100, config);
+
+            assert!(validate(&distance, &solution));
+            assert!(evaluate(&distance, &solution) < evaluate(&distance, &initial));
+        }
+    }
 }
@@ -0,0 +1,724 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    path::PathBuf,
+    time::Instant,
+};
+
+use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
+use rayon::prelude::*;
+
+use crate::{
+    activation_order::ActivationOrder,
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    evaluate::evaluate,
+    improvement_strategy::ImprovementStrategy,
+    neighbor_table::NeighborTable,
+    opt2::{self, Opt2Config},
+    solution::Solution,
+    stop_reason::StopReason,
+};
+
+pub struct EAXConfig {
+    pub use_neighbor_cache: bool,
+    pub cache_filepath: PathBuf,
+    pub debug: bool,
+    // None なら制限なし
+    pub time_ms: Option<u128>,
+    pub max_generation: Option<usize>,
+    pub population_size: usize,
+    // 1 組の親から生成する子の数
+    pub no_children: usize,
+    // 最良解がこの世代数だけ更新されなければ収束したとみなす
+    pub max_stagnation: usize,
+    pub neighbor_size: usize,
+}
+
+const NONE: u32 = u32::MAX;
+
+// 辺の入れ替え後に隣接リストの空きを埋める
+fn add_neighbor(adjacent: &mut [u32; 2], id: u32) {
+    if adjacent[0] == NONE {
+        adjacent[0] = id;
+    } else {
+        debug_assert_eq!(adjacent[1], NONE);
+        adjacent[1] = id;
+    }
+}
+
+fn remove_neighbor(adjacent: &mut [u32; 2], id: u32) {
+    if adjacent[0] == id {
+        adjacent[0] = NONE;
+    } else {
+        debug_assert_eq!(adjacent[1], id);
+        adjacent[1] = NONE;
+    }
+}
+
+fn edge_key(id1: u32, id2: u32) -> (u32, u32) {
+    (id1.min(id2), id1.max(id2))
+}
+
+// 集団の各個体は隣接リストで持つ
+// 子の生成では数本の辺しか変わらないので、順序を持つより差分の適用と巻き戻しが安い
+struct Individual {
+    adjacent: Vec<[u32; 2]>,
+    eval: i64,
+}
+
+impl Individual {
+    fn new(distance: &impl DistanceFunction, solution: &impl Solution) -> Individual {
+        let adjacent = (0..solution.len() as u32)
+            .map(|id| [solution.prev(id), solution.next(id)])
+            .collect();
+        Individual {
+            adjacent,
+            eval: evaluate(distance, solution),
+        }
+    }
+
+    fn to_order(&self) -> Vec<u32> {
+        let n = self.adjacent.len();
+        let mut order = Vec::with_capacity(n);
+        let mut prev = self.adjacent[0][1];
+        let mut id = 0;
+        loop {
+            order.push(id);
+            let [v1, v2] = self.adjacent[id as usize];
+            let next = if v1 == prev { v2 } else { v1 };
+            prev = id;
+            id = next;
+            if id == 0 {
+                break;
+            }
+        }
+        assert_eq!(order.len(), n);
+        order
+    }
+
+    fn to_solution(&self) -> ArraySolution {
+        ArraySolution::from_array(self.to_order())
+    }
+}
+
+// 集団内で各辺を持つ個体数
+struct EdgeFrequency {
+    population_size: f64,
+    table: HashMap<(u32, u32), u32>,
+}
+
+impl EdgeFrequency {
+    fn new(population: &[Individual]) -> EdgeFrequency {
+        let mut table = HashMap::new();
+        for individual in population.iter() {
+            for (id, adjacent) in individual.adjacent.iter().enumerate() {
+                for &next in adjacent.iter() {
+                    if (id as u32) < next {
+                        *table.entry((id as u32, next)).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        EdgeFrequency {
+            population_size: population.len() as f64,
+            table,
+        }
+    }
+
+    fn get(&self, id1: u32, id2: u32) -> u32 {
+        self.table.get(&edge_key(id1, id2)).cloned().unwrap_or(0)
+    }
+
+    fn entropy_term(&self, frequency: u32) -> f64 {
+        if frequency == 0 {
+            0.0
+        } else {
+            let p = frequency as f64 / self.population_size;
+            -p * p.ln()
+        }
+    }
+
+    // 辺の削除・追加による集団のエッジエントロピーの変化量
+    fn delta_entropy(&self, removed: &[(u32, u32)], added: &[(u32, u32)]) -> f64 {
+        let mut delta = 0.0;
+        for &(id1, id2) in removed.iter() {
+            let frequency = self.get(id1, id2);
+            delta += self.entropy_term(frequency - 1) - self.entropy_term(frequency);
+        }
+        for &(id1, id2) in added.iter() {
+            let frequency = self.get(id1, id2);
+            delta += self.entropy_term(frequency + 1) - self.entropy_term(frequency);
+        }
+        delta
+    }
+
+    fn update(&mut self, removed: &[(u32, u32)], added: &[(u32, u32)]) {
+        for &(id1, id2) in removed.iter() {
+            let key = edge_key(id1, id2);
+            let frequency = self.table.get_mut(&key).unwrap();
+            *frequency -= 1;
+            if *frequency == 0 {
+                self.table.remove(&key);
+            }
+        }
+        for &(id1, id2) in added.iter() {
+            *self.table.entry(edge_key(id1, id2)).or_insert(0) += 1;
+        }
+    }
+}
+
+// 親 A と親 B で異なる辺を、A の辺と B の辺が交互に現れる閉路 (AB-cycle) に分解する
+// 各 AB-cycle は [c0, c1, ...] で、(c0, c1) が A の辺、(c1, c2) が B の辺、... と続く
+fn build_ab_cycle_list(
+    parent_a: &Individual,
+    parent_b: &Individual,
+    rng: &mut ThreadRng,
+) -> Vec<Vec<u32>> {
+    let n = parent_a.adjacent.len();
+
+    // 片方の親にしかない辺を、頂点ごとに最大 2 本持つ
+    let mut rest_a = vec![[NONE; 2]; n];
+    let mut rest_b = vec![[NONE; 2]; n];
+    let mut start_list = vec![];
+    for id in 0..n {
+        for &next in parent_a.adjacent[id].iter() {
+            if !parent_b.adjacent[id].contains(&next) {
+                add_neighbor(&mut rest_a[id], next);
+            }
+        }
+        for &next in parent_b.adjacent[id].iter() {
+            if !parent_a.adjacent[id].contains(&next) {
+                add_neighbor(&mut rest_b[id], next);
+            }
+        }
+        if rest_a[id][0] != NONE {
+            start_list.push(id as u32);
+        }
+    }
+    start_list.shuffle(rng);
+
+    let pick = |rest: &mut Vec<[u32; 2]>, id: u32, rng: &mut ThreadRng| {
+        let adjacent = rest[id as usize];
+        let next = if adjacent[1] == NONE || (adjacent[0] != NONE && rng.gen_bool(0.5)) {
+            adjacent[0]
+        } else {
+            adjacent[1]
+        };
+        remove_neighbor(&mut rest[id as usize], next);
+        remove_neighbor(&mut rest[next as usize], id);
+        next
+    };
+
+    let mut cycle_list = vec![];
+    // 各頂点が現在の経路上のどの位置に現れているか
+    let mut position_list: HashMap<u32, Vec<usize>> = HashMap::new();
+    for start in start_list {
+        while rest_a[start as usize] != [NONE; 2] {
+            let mut path = vec![start];
+            // kind[i] は path[i] -> path[i + 1] の辺が A の辺か
+            let mut kind: Vec<bool> = vec![];
+            position_list.clear();
+            position_list.insert(start, vec![0]);
+
+            loop {
+                let current = *path.last().unwrap();
+                let use_a = kind.last().is_none_or(|&k| !k);
+                let next = if use_a {
+                    pick(&mut rest_a, current, rng)
+                } else {
+                    pick(&mut rest_b, current, rng)
+                };
+                kind.push(use_a);
+
+                // next から出る辺が今回と逆の種類なら、そこで交互の閉路が閉じる
+                let close = position_list
+                    .get(&next)
+                    .and_then(|list| list.iter().rev().find(|&&i| kind[i] != use_a).cloned());
+
+                match close {
+                    Some(i) => {
+                        let mut cycle = path[i..].to_vec();
+                        if !kind[i] {
+                            cycle.rotate_left(1);
+                        }
+                        cycle_list.push(cycle);
+
+                        for &id in path[i + 1..].iter() {
+                            position_list.get_mut(&id).unwrap().pop();
+                        }
+                        path.truncate(i + 1);
+                        kind.truncate(i);
+                        if path.len() == 1 {
+                            break;
+                        }
+                    }
+                    None => {
+                        path.push(next);
+                        position_list.entry(next).or_default().push(path.len() - 1);
+                    }
+                }
+            }
+        }
+    }
+    cycle_list
+}
+
+// 子の候補。親 A に対する差分として持つ
+struct Child {
+    delta_eval: i64,
+    removed: Vec<(u32, u32)>,
+    added: Vec<(u32, u32)>,
+    adjacent_list: Vec<(u32, [u32; 2])>,
+}
+
+// 親 A に AB-cycle を適用して部分巡回路を統合する作業領域
+struct Workspace {
+    order: Vec<u32>,
+    position: Vec<u32>,
+    // 変更した頂点の元の隣接リスト
+    backup: Vec<(u32, [u32; 2])>,
+    touched: Vec<bool>,
+}
+
+impl Workspace {
+    fn new(n: usize) -> Workspace {
+        Workspace {
+            order: vec![],
+            position: vec![0; n],
+            backup: vec![],
+            touched: vec![false; n],
+        }
+    }
+
+    fn set_parent(&mut self, parent: &Individual) {
+        self.order = parent.to_order();
+        for (index, &id) in self.order.iter().enumerate() {
+            self.position[id as usize] = index as u32;
+        }
+    }
+
+    // 辺を削除してから追加する。変更する頂点の隣接リストは巻き戻し用に保存しておく
+    fn modify(&mut self, parent: &mut Individual, removed: &[(u32, u32)], added: &[(u32, u32)]) {
+        for &(id1, id2) in removed.iter() {
+            for id in [id1, id2] {
+                if !self.touched[id as usize] {
+                    self.touched[id as usize] = true;
+                    self.backup.push((id, parent.adjacent[id as usize]));
+                }
+            }
+            remove_neighbor(&mut parent.adjacent[id1 as usize], id2);
+            remove_neighbor(&mut parent.adjacent[id2 as usize], id1);
+        }
+        for &(id1, id2) in added.iter() {
+            add_neighbor(&mut parent.adjacent[id1 as usize], id2);
+            add_neighbor(&mut parent.adjacent[id2 as usize], id1);
+        }
+    }
+
+    // AB-cycle で作った中間解の部分巡回路を、2-opt 型の繋ぎ替えで 1 つに統合した子を作る
+    // 親 A は呼び出し後に元に戻る
+    fn make_child(
+        &mut self,
+        distance: &impl DistanceFunction,
+        neighbor_table: &NeighborTable,
+        parent: &mut Individual,
+        cycle: &[u32],
+    ) -> Child {
+        let n = self.order.len();
+        let len = cycle.len();
+
+        let removed_list = (0..len)
+            .step_by(2)
+            .map(|j| (cycle[j], cycle[j + 1]))
+            .collect::<Vec<_>>();
+        let added_list = (1..len)
+            .step_by(2)
+            .map(|j| (cycle[j], cycle[(j + 1) % len]))
+            .collect::<Vec<_>>();
+        let mut delta_eval = added_list
+            .iter()
+            .map(|&(id1, id2)| distance.distance(id1, id2))
+            .sum::<i64>()
+            - removed_list
+                .iter()
+                .map(|&(id1, id2)| distance.distance(id1, id2))
+                .sum::<i64>();
+
+        // 削除した辺の、親 A の順序での位置
+        let mut cut_list = removed_list
+            .iter()
+            .map(|&(id1, id2)| {
+                let (p1, p2) = (self.position[id1 as usize], self.position[id2 as usize]);
+                if (p1 as usize + 1) % n == p2 as usize {
+                    p1
+                } else {
+                    p2
+                }
+            })
+            .collect::<Vec<_>>();
+        cut_list.sort_unstable();
+        self.modify(parent, &removed_list, &added_list);
+
+        // 親 A の順序で、削除した辺の間の区間 (segment) 単位で部分巡回路を管理する
+        let k = cut_list.len();
+        let start_of = |j: usize| (cut_list[j] as usize + 1) % n;
+        let end_of = |j: usize| cut_list[(j + 1) % k] as usize;
+        let segment_of = |id: u32, position: &Vec<u32>| {
+            let index = cut_list.partition_point(|&cut| cut < position[id as usize]);
+            if index == 0 {
+                k - 1
+            } else {
+                index - 1
+            }
+        };
+
+        let mut label = vec![usize::MAX; k];
+        let mut size_list = vec![];
+        for first in 0..k {
+            if label[first] != usize::MAX {
+                continue;
+            }
+            let subtour = size_list.len();
+            size_list.push(0);
+
+            let mut segment = first;
+            let mut current = self.order[start_of(first)];
+            let mut prev = NONE;
+            loop {
+                label[segment] = subtour;
+                let (start, end) = (start_of(segment), end_of(segment));
+                size_list[subtour] += (end + n - start) % n + 1;
+
+                let (start_id, end_id) = (self.order[start], self.order[end]);
+                let (exit, inner) = if start_id == end_id {
+                    (current, prev)
+                } else if current == start_id {
+                    (end_id, self.order[(end + n - 1) % n])
+                } else {
+                    (start_id, self.order[(start + 1) % n])
+                };
+                let [v1, v2] = parent.adjacent[exit as usize];
+                let next = if v1 == inner { v2 } else { v1 };
+                let next_segment = segment_of(next, &self.position);
+                if label[next_segment] == subtour {
+                    break;
+                }
+                prev = exit;
+                current = next;
+                segment = next_segment;
+            }
+        }
+
+        // 部分巡回路ごとの segment の一覧と、頂点数の小さい順に取り出すヒープ
+        let mut member_list = vec![vec![]; size_list.len()];
+        for (segment, &subtour) in label.iter().enumerate() {
+            member_list[subtour].push(segment);
+        }
+        let mut queue = size_list
+            .iter()
+            .enumerate()
+            .map(|(subtour, &size)| Reverse((size, subtour)))
+            .collect::<BinaryHeap<_>>();
+
+        let mut no_subtour = size_list.len();
+        while no_subtour > 1 {
+            // 最も小さい部分巡回路を、近くの別の部分巡回路と繋ぐ
+            let Reverse((size, target)) = queue.pop().unwrap();
+            if size != size_list[target] {
+                continue;
+            }
+
+            type Candidate = (i64, u32, [(u32, u32); 2], [(u32, u32); 2]);
+            let mut best: Option<Candidate> = None;
+            let try_connect =
+                |v: u32, w: u32, parent: &Individual, best: &mut Option<Candidate>| {
+                    let distance_vw = distance.distance(v, w);
+                    for &v2 in parent.adjacent[v as usize].iter() {
+                        let distance_vv2 = distance.distance(v, v2);
+                        let distance_v2w = distance.distance(v2, w);
+                        for &w2 in parent.adjacent[w as usize].iter() {
+                            let base = -distance_vv2 - distance.distance(w, w2);
+                            for (delta, added) in [
+                                (
+                                    base + distance_vw + distance.distance(v2, w2),
+                                    [(v, w), (v2, w2)],
+                                ),
+                                (
+                                    base + distance.distance(v, w2) + distance_v2w,
+                                    [(v, w2), (v2, w)],
+                                ),
+                            ] {
+                                if best.is_none_or(|(best_delta, ..)| delta < best_delta) {
+                                    *best = Some((delta, w, [(v, v2), (w, w2)], added));
+                                }
+                            }
+                        }
+                    }
+                };
+            for &segment in member_list[target].iter() {
+                let (start, end) = (start_of(segment), end_of(segment));
+                for offset in 0..=(end + n - start) % n {
+                    let v = self.order[(start + offset) % n];
+                    for &w in neighbor_table.neighbor_list(v).iter() {
+                        if label[segment_of(w, &self.position)] != target {
+                            try_connect(v, w, parent, &mut best);
+                        }
+                    }
+                }
+            }
+            if best.is_none() {
+                // 近傍リストに他の部分巡回路の頂点がなければ全頂点から探す
+                let v = self.order[start_of(member_list[target][0])];
+                for w in 0..n as u32 {
+                    if label[segment_of(w, &self.position)] != target {
+                        try_connect(v, w, parent, &mut best);
+                    }
+                }
+            }
+
+            let (delta, w, removed, added) = best.unwrap();
+            delta_eval += delta;
+            self.modify(parent, &removed, &added);
+
+            // segment の少ない方の番号を付け替える
+            let mut merged = label[segment_of(w, &self.position)];
+            let mut absorbed = target;
+            if member_list[merged].len() < member_list[absorbed].len() {
+                (merged, absorbed) = (absorbed, merged);
+            }
+            for segment in std::mem::take(&mut member_list[absorbed]) {
+                label[segment] = merged;
+                member_list[merged].push(segment);
+            }
+            size_list[merged] += size_list[absorbed];
+            size_list[absorbed] = 0;
+            queue.push(Reverse((size_list[merged], merged)));
+            no_subtour -= 1;
+        }
+
+        // 差分を記録して親 A を元に戻す
+        let mut removed = vec![];
+        let mut added = vec![];
+        let mut adjacent_list = vec![];
+        for &(id, old) in self.backup.iter().rev() {
+            let new = parent.adjacent[id as usize];
+            for &next in old.iter() {
+                if id < next && !new.contains(&next) {
+                    removed.push((id, next));
+                }
+            }
+            for &next in new.iter() {
+                if id < next && !old.contains(&next) {
+                    added.push((id, next));
+                }
+            }
+            adjacent_list.push((id, new));
+            parent.adjacent[id as usize] = old;
+            self.touched[id as usize] = false;
+        }
+        self.backup.clear();
+
+        Child {
+            delta_eval,
+            removed,
+            added,
+            adjacent_list,
+        }
+    }
+}
+
+// Edge Assembly Crossover による遺伝的アルゴリズム
+// 初期集団は random な初期解からの 2-opt 局所解で作り、
+// 子の選択は集団のエッジエントロピーを保つ評価 (Nagata & Kobayashi) で行う
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    config: EAXConfig,
+) -> (ArraySolution, StopReason) {
+    let n = distance.dimension() as usize;
+
+    let start = Instant::now();
+
+    let neighbor_table = NeighborTable::load_or_build(
+        distance,
+        config.neighbor_size,
+        config.use_neighbor_cache,
+        &config.cache_filepath,
+    );
+
+    let mut population = (0..config.population_size)
+        .into_par_iter()
+        .map(|_| {
+            let mut rng = rand::thread_rng();
+            let mut content = (0..n as u32).collect::<Vec<_>>();
+            content.shuffle(&mut rng);
+            let opt2_config = Opt2Config {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: None,
+                max_iteration: None,
+                improvement_strategy: ImprovementStrategy::FirstImprovement,
+                activation_order: ActivationOrder::Random,
+            };
+            let (solution, _) = opt2::solve_with_neighbor_table(
+                distance,
+                &neighbor_table,
+                ArraySolution::from_array(content),
+                opt2_config,
+            );
+            Individual::new(distance, &solution)
+        })
+        .collect::<Vec<_>>();
+
+    let mut rng = rand::thread_rng();
+    let mut frequency = EdgeFrequency::new(&population);
+    let mut workspace = Workspace::new(n);
+
+    let best_eval_of =
+        |population: &[Individual]| population.iter().map(|ind| ind.eval).min().unwrap();
+    let mut best_eval = best_eval_of(&population);
+    let mut no_stagnation = 0;
+
+    let mut stop_reason = StopReason::LocalOptimum;
+
+    'generation: for generation in 0.. {
+        let mut index_list = (0..population.len()).collect::<Vec<_>>();
+        index_list.shuffle(&mut rng);
+
+        let mut converged = true;
+        for i in 0..index_list.len() {
+            if config
+                .time_ms
+                .is_some_and(|time_ms| start.elapsed().as_millis() > time_ms)
+            {
+                stop_reason = StopReason::TimeLimit;
+                break 'generation;
+            }
+
+            let (index_a, index_b) = (index_list[i], index_list[(i + 1) % index_list.len()]);
+            let mut cycle_list =
+                build_ab_cycle_list(&population[index_a], &population[index_b], &mut rng);
+            if cycle_list.is_empty() {
+                continue;
+            }
+            converged = false;
+            cycle_list.shuffle(&mut rng);
+
+            let parent = &mut population[index_a];
+            workspace.set_parent(parent);
+
+            // 長さが減るなら、エントロピーの減少あたりの改善量が最も大きい子を選ぶ
+            let mut best_child: Option<(f64, Child)> = None;
+            for cycle in cycle_list.iter().take(config.no_children) {
+                let child = workspace.make_child(distance, &neighbor_table, parent, cycle);
+                let delta_entropy = frequency.delta_entropy(&child.removed, &child.added);
+                let score = if delta_entropy < 0.0 {
+                    child.delta_eval as f64 / delta_entropy
+                } else {
+                    -child.delta_eval as f64 / 1e-9
+                };
+                if score > 0.0 && best_child.as_ref().is_none_or(|(best, _)| score > *best) {
+                    best_child = Some((score, child));
+                }
+            }
+
+            if let Some((_, child)) = best_child {
+                for &(id, adjacent) in child.adjacent_list.iter() {
+                    parent.adjacent[id as usize] = adjacent;
+                }
+                parent.eval += child.delta_eval;
+                frequency.update(&child.removed, &child.added);
+                debug_assert_eq!(parent.eval, evaluate(distance, &parent.to_solution()));
+            }
+        }
+
+        let eval = best_eval_of(&population);
+        if eval < best_eval {
+            best_eval = eval;
+            no_stagnation = 0;
+        } else {
+            no_stagnation += 1;
+        }
+
+        if config.debug {
+            let average =
+                population.iter().map(|ind| ind.eval).sum::<i64>() as f64 / population.len() as f64;
+            eprintln!("-----");
+            eprintln!("generation: {}", generation);
+            eprintln!("best eval: {}", best_eval);
+            eprintln!("average eval: {:.1}", average);
+        }
+
+        if converged || no_stagnation >= config.max_stagnation {
+            break;
+        }
+        if config
+            .max_generation
+            .is_some_and(|max_generation| generation + 1 >= max_generation)
+        {
+            stop_reason = StopReason::IterationLimit;
+            break;
+        }
+    }
+
+    let best = population.iter().min_by_key(|ind| ind.eval).unwrap();
+    (best.to_solution(), stop_reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::{seq::SliceRandom, thread_rng};
+
+    use crate::{
+        activation_order::ActivationOrder,
+        array_solution::ArraySolution,
+        evaluate::{evaluate, validate},
+        improvement_strategy::ImprovementStrategy,
+        opt2::{self, Opt2Config},
+        test_util::random_euclid_distance,
+    };
+
+    use super::{solve, EAXConfig};
+
+    #[test]
+    fn test_eax() {
+        const SIZE: usize = 200;
+        let mut rng = thread_rng();
+
+        let distance = random_euclid_distance(SIZE);
+
+        let config = EAXConfig {
+            use_neighbor_cache: false,
+            cache_filepath: PathBuf::new(),
+            debug: false,
+            time_ms: None,
+            max_generation: None,
+            population_size: 30,
+            no_children: 10,
+            max_stagnation: 20,
+            neighbor_size: 10,
+        };
+        let (solution, _) = solve(&distance, config);
+
+        assert!(validate(&distance, &solution));
+
+        // 2-opt の局所解よりは十分良くなっている
+        let mut content = (0..SIZE as u32).collect::<Vec<_>>();
+        content.shuffle(&mut rng);
+        let opt2_config = Opt2Config {
+            use_neighbor_cache: false,
+            cache_filepath: PathBuf::new(),
+            debug: false,
+            time_ms: None,
+            max_iteration: None,
+            improvement_strategy: ImprovementStrategy::FirstImprovement,
+            activation_order: ActivationOrder::Random,
+        };
+        let (opt2_solution, _) =
+            opt2::solve(&distance, ArraySolution::from_array(content), opt2_config);
+        assert!(evaluate(&distance, &solution) < evaluate(&distance, &opt2_solution));
+    }
+}
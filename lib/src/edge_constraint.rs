@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::{
+    array_solution::ArraySolution, distance::DistanceFunction, euclid_distance::read_tsplib_section,
+};
+
+// 固定辺と禁止辺は is_fixed と is_forbidden で判定し、局所探索はそれに従って辺を張り替える
+// 距離にも次のコストを付けておき、制約を見ない解法や初期解でも固定辺が残り、禁止辺が取り除かれるようにする
+pub const FIXED_EDGE_COST: i64 = -(1 << 40);
+pub const FORBIDDEN_EDGE_COST: i64 = 1 << 40;
+
+// removed の辺を消して added の辺を張る変更が、固定辺と禁止辺の制約を守るか
+pub(crate) fn is_allowed_change(
+    distance: &impl DistanceFunction,
+    removed: &[(u32, u32)],
+    added: &[(u32, u32)],
+) -> bool {
+    removed
+        .iter()
+        .all(|&(id1, id2)| !distance.is_fixed(id1, id2))
+        && added
+            .iter()
+            .all(|&(id1, id2)| !distance.is_forbidden(id1, id2))
+}
+
+fn normalize((id1, id2): (u32, u32)) -> (u32, u32) {
+    (id1.min(id2), id1.max(id2))
+}
+
+// 固定辺と禁止辺を持つ問題
+// 2-opt, 3-opt, LKH とその kick は固定辺を消さず、禁止辺を張らない
+pub struct ConstrainedDistance<'a, T: DistanceFunction> {
+    ref_distance: &'a T,
+    fixed: HashSet<(u32, u32)>,
+    forbidden: HashSet<(u32, u32)>,
+}
+
+impl<'a, T: DistanceFunction> ConstrainedDistance<'a, T> {
+    pub fn new(
+        ref_distance: &'a T,
+        fixed_edge_list: &[(u32, u32)],
+        forbidden_edge_list: &[(u32, u32)],
+    ) -> ConstrainedDistance<'a, T> {
+        let fixed = fixed_edge_list
+            .iter()
+            .map(|&edge| normalize(edge))
+            .collect::<HashSet<_>>();
+        let forbidden = forbidden_edge_list
+            .iter()
+            .map(|&edge| normalize(edge))
+            .collect::<HashSet<_>>();
+        assert!(fixed.is_disjoint(&forbidden));
+        ConstrainedDistance {
+            ref_distance,
+            fixed,
+            forbidden,
+        }
+    }
+
+    // 固定辺を全て含み、禁止辺をなるべく含まない初期解
+    // 固定辺で繋がるパスを、禁止辺を避けながら順に繋ぐ
+    pub fn initial_solution(&self) -> ArraySolution {
+        let n = self.ref_distance.dimension() as usize;
+        let mut adjacent = vec![vec![]; n];
+        for &(id1, id2) in self.fixed.iter() {
+            adjacent[id1 as usize].push(id2);
+            adjacent[id2 as usize].push(id1);
+        }
+        assert!(
+            adjacent.iter().all(|list| list.len() <= 2),
+            "a vertex has more than two fixed edges"
+        );
+
+        // 固定辺のパスを列挙する。端点 (次数 1 以下) から辿る
+        let mut visited = vec![false; n];
+        let mut path_list = vec![];
+        for start in 0..n {
+            if visited[start] || adjacent[start].len() == 2 {
+                continue;
+            }
+            let mut path = vec![start as u32];
+            visited[start] = true;
+            let mut id = start;
+            while let Some(&next) = adjacent[id].iter().find(|&&v| !visited[v as usize]) {
+                visited[next as usize] = true;
+                path.push(next);
+                id = next as usize;
+            }
+            path_list.push(path);
+        }
+        assert!(
+            visited.iter().all(|&v| v) || path_list.is_empty(),
+            "fixed edges form a subtour"
+        );
+        if path_list.is_empty() {
+            // 固定辺だけで巡回路になっている
+            let mut vertex_array = vec![0];
+            let mut prev = 0;
+            let mut id = adjacent[0][0];
+            while id != 0 {
+                vertex_array.push(id);
+                let next = adjacent[id as usize]
+                    .iter()
+                    .cloned()
+                    .find(|&v| v != prev)
+                    .unwrap();
+                prev = id;
+                id = next;
+            }
+            assert_eq!(vertex_array.len(), n, "fixed edges form a subtour");
+            return ArraySolution::from_array(vertex_array);
+        }
+        assert!(visited.iter().all(|&v| v), "fixed edges form a subtour");
+
+        let mut vertex_array = path_list.swap_remove(0);
+        while !path_list.is_empty() {
+            let last = *vertex_array.last().unwrap();
+            let (index, reverse) = (0..path_list.len())
+                .flat_map(|index| [(index, false), (index, true)])
+                .find(|&(index, reverse)| {
+                    let path = &path_list[index];
+                    let first = if reverse {
+                        path[path.len() - 1]
+                    } else {
+                        path[0]
+                    };
+                    !self.is_forbidden(last, first)
+                })
+                .unwrap_or((0, false));
+            let mut path = path_list.swap_remove(index);
+            if reverse {
+                path.reverse();
+            }
+            vertex_array.extend(path);
+        }
+        ArraySolution::from_array(vertex_array)
+    }
+}
+
+impl<'a, T: DistanceFunction> DistanceFunction for ConstrainedDistance<'a, T> {
+    fn distance(&self, id1: u32, id2: u32) -> i64 {
+        if self.is_fixed(id1, id2) {
+            FIXED_EDGE_COST
+        } else if self.is_forbidden(id1, id2) {
+            FORBIDDEN_EDGE_COST
+        } else {
+            self.ref_distance.distance(id1, id2)
+        }
+    }
+
+    fn dimension(&self) -> u32 {
+        self.ref_distance.dimension()
+    }
+
+    fn name(&self) -> String {
+        self.ref_distance.name()
+    }
+
+    fn is_symmetric(&self) -> bool {
+        self.ref_distance.is_symmetric()
+    }
+
+    fn is_fixed(&self, id1: u32, id2: u32) -> bool {
+        self.fixed.contains(&normalize((id1, id2)))
+    }
+
+    fn is_forbidden(&self, id1: u32, id2: u32) -> bool {
+        self.forbidden.contains(&normalize((id1, id2)))
+    }
+
+    fn fixed_edge_list(&self) -> Vec<(u32, u32)> {
+        self.fixed.iter().cloned().collect()
+    }
+
+    fn coordinate(&self, id: u32) -> Option<(i64, i64)> {
+        self.ref_distance.coordinate(id)
+    }
+}
+
+// TSPLIB 形式の FIXED_EDGES_SECTION を読む。頂点番号は 0 始まりに直す
+pub fn load_fixed_edges_tsplib(filepath: &PathBuf) -> Vec<(u32, u32)> {
+    read_tsplib_section(filepath, "FIXED_EDGES_SECTION")
+        .into_iter()
+        .flatten()
+        .take_while(|&id| id != -1)
+        .collect::<Vec<_>>()
+        .chunks_exact(2)
+        .map(|pair| ((pair[0] - 1) as u32, (pair[1] - 1) as u32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::PathBuf};
+
+    use crate::{
+        activation_order::ActivationOrder,
+        distance::DistanceFunction,
+        euclid_distance::EuclidDistance,
+        evaluate::validate,
+        exact,
+        improvement_strategy::ImprovementStrategy,
+        lkh::{self, LKHConfig},
+        opt2::{self, Opt2Config},
+        opt3::{self, Opt3Config},
+        test_util::{random_euclid_distance, temp_filepath},
+    };
+
+    use super::{load_fixed_edges_tsplib, ConstrainedDistance, FIXED_EDGE_COST};
+
+    #[test]
+    fn test_fixed_edge_cost() {
+        const SIZE: usize = 10;
+        let distance = random_euclid_distance(SIZE);
+        let constrained = ConstrainedDistance::new(&distance, &[(0, 5), (7, 2)], &[]);
+        assert_eq!(constrained.distance(5, 0), FIXED_EDGE_COST);
+
+        // 制約を見ない解法でも、固定辺のコストで固定辺が残る
+        let (solution, _) = exact::solve_dp(&constrained);
+        assert!(validate(&constrained, &solution));
+    }
+
+    #[test]
+    fn test_load_fixed_edges_tsplib() {
+        let filepath = temp_filepath("edge_constraint_test.tsp");
+        let mut f = std::fs::File::create(&filepath).unwrap();
+        writeln!(
+            f,
+            "NAME: test\nTYPE: TSP\nDIMENSION: 4\nEDGE_WEIGHT_TYPE: EUC_2D\n\
+             NODE_COORD_SECTION\n1 0 0\n2 0 1\n3 1 1\n4 1 0\n\
+             FIXED_EDGES_SECTION :\n1 3\n2 4\n-1\nEOF"
+        )
+        .unwrap();
+        drop(f);
+
+        assert_eq!(load_fixed_edges_tsplib(&filepath), vec![(0, 2), (1, 3)]);
+        assert_eq!(EuclidDistance::load_tsplib(&filepath).dimension(), 4);
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn test_constrained_local_search() {
+        const SIZE: usize = 200;
+
+        let distance = random_euclid_distance(SIZE);
+
+        // 遠い頂点同士を固定し、近い頂点同士を禁止する
+        let fixed_edge_list = (0..10).map(|i| (i, i + 100)).collect::<Vec<_>>();
+        let forbidden_edge_list = (0..SIZE as u32)
+            .filter_map(|i| {
+                (0..SIZE as u32)
+                    .filter(|&j| j != i && !fixed_edge_list.contains(&(i.min(j), i.max(j))))
+                    .min_by_key(|&j| distance.distance(i, j))
+                    .map(|j| (i, j))
+            })
+            .filter(|&(i, j)| !fixed_edge_list.contains(&(i.min(j), i.max(j))))
+            .collect::<Vec<_>>();
+        let constrained =
+            ConstrainedDistance::new(&distance, &fixed_edge_list, &forbidden_edge_list);
+        let initial = constrained.initial_solution();
+        assert!(validate(&constrained, &initial));
+
+        let (solution, _) = opt2::solve(
+            &constrained,
+            initial.clone(),
+            Opt2Config {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: None,
+                max_iteration: None,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+            },
+        );
+        assert!(validate(&constrained, &solution));
+
+        let (solution, _) = opt3::solve(
+            &constrained,
+            initial.clone(),
+            Opt3Config {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: None,
+                max_iteration: None,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+                or_opt_max_len: 3,
+            },
+        );
+        assert!(validate(&constrained, &solution));
+
+        let solution = lkh::solve(
+            &constrained,
+            initial,
+            LKHConfig {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: 500,
+                start_kick_step: 5,
+                kick_step_diff: 5,
+                end_kick_step: 20,
+                fail_count_threashold: 10,
+                max_depth: 5,
+                use_non_sequential_move: true,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+            },
+        );
+        assert!(validate(&constrained, &solution));
+    }
+}
@@ -31,12 +31,21 @@ enum TSPLibFormatCode {
 }
 
 impl EuclidDistance {
+    // (y, x) の座標列から生成する
+    pub fn new(coordinate_list: &[(i64, i64)], name: String) -> EuclidDistance {
+        let point_list = coordinate_list
+            .iter()
+            .map(|&(y, x)| Point::new(y, x))
+            .collect();
+        EuclidDistance { point_list, name }
+    }
+
     pub fn load_tsplib(filepath: &PathBuf) -> EuclidDistance {
         let f = File::open(filepath).unwrap();
         let reader = BufReader::new(f);
         let name = filepath.file_name().unwrap().to_str().unwrap().to_string();
 
-        let mut dimension = std::u32::MAX;
+        let mut dimension = u32::MAX;
         let mut point_list = vec![];
         let mut mode = TSPLibFormatCode::Config;
 
@@ -55,9 +64,14 @@ impl EuclidDistance {
                         mode = TSPLibFormatCode::Coordinate;
                     }
                 }
+                TSPLibFormatCode::Coordinate if line.contains("SECTION") => {
+                    // 座標以外のセクション (FIXED_EDGES_SECTION など) は読み飛ばす
+                    mode = TSPLibFormatCode::Config;
+                }
+                TSPLibFormatCode::Coordinate if line.trim().is_empty() => {}
                 TSPLibFormatCode::Coordinate => {
                     let num_token_list = line
-                        .split(" ")
+                        .split_whitespace()
                         .map(|v| v.parse::<i64>().unwrap())
                         .collect::<Vec<_>>();
                     let y = num_token_list[1];
@@ -71,6 +85,55 @@ impl EuclidDistance {
     }
 }
 
+// TSPLIB 形式のヘッダから key の値を読む
+pub(crate) fn read_tsplib_header(filepath: &PathBuf, key: &str) -> Option<String> {
+    let f = File::open(filepath).unwrap();
+    let reader = BufReader::new(f);
+    for line in reader.lines() {
+        let line = line.unwrap();
+        if line.contains("SECTION") || line.contains("EOF") {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim() == key {
+                return Some(value.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+// TSPLIB 形式の section の各行を、数値の列として読む。見出しは "GTSP_SET_SECTION:" のような表記揺れも許す
+// 空行は飛ばし、次の section か EOF で終わる
+pub(crate) fn read_tsplib_section(filepath: &PathBuf, section: &str) -> Vec<Vec<i64>> {
+    let f = File::open(filepath).unwrap();
+    let reader = BufReader::new(f);
+
+    let mut in_section = false;
+    let mut row_list = vec![];
+    for line in reader.lines() {
+        let line = line.unwrap();
+        if line.contains("EOF") {
+            break;
+        }
+        if in_section {
+            if line.contains("SECTION") {
+                break;
+            }
+            let row = line
+                .split_whitespace()
+                .map(|token| token.parse::<i64>().unwrap())
+                .collect::<Vec<_>>();
+            if !row.is_empty() {
+                row_list.push(row);
+            }
+        } else if line.contains(section) {
+            in_section = true;
+        }
+    }
+    row_list
+}
+
 impl DistanceFunction for EuclidDistance {
     fn distance(&self, id1: u32, id2: u32) -> i64 {
         self.point_list[id1 as usize].distance(&self.point_list[id2 as usize])
@@ -83,4 +146,9 @@ impl DistanceFunction for EuclidDistance {
     fn name(&self) -> String {
         self.name.to_string()
     }
+
+    fn coordinate(&self, id: u32) -> Option<(i64, i64)> {
+        let point = &self.point_list[id as usize];
+        Some((point.y, point.x))
+    }
 }
@@ -10,3 +10,29 @@ pub fn evaluate(distance: &impl DistanceFunction, solution: &impl Solution) -> i
     }
     sum
 }
+
+// 全頂点を一度ずつ通る巡回路で、固定辺を全て含み、禁止辺を含まないか
+pub fn validate(distance: &impl DistanceFunction, solution: &impl Solution) -> bool {
+    let n = distance.dimension();
+    if solution.len() != n as usize {
+        return false;
+    }
+    let mut visited = vec![false; n as usize];
+    let mut id = 0;
+    for _iter in 0..n {
+        if visited[id as usize] {
+            return false;
+        }
+        visited[id as usize] = true;
+        let next = solution.next(id);
+        if distance.is_forbidden(id, next) {
+            return false;
+        }
+        id = next;
+    }
+    id == 0
+        && distance
+            .fixed_edge_list()
+            .iter()
+            .all(|&(id1, id2)| solution.next(id1) == id2 || solution.prev(id1) == id2)
+}
@@ -0,0 +1,566 @@
+use std::time::Instant;
+
+use crate::{
+    array_solution::ArraySolution, distance::DistanceFunction, evaluate::evaluate,
+    solution::Solution,
+};
+
+// bit DP で解く問題の最大頂点数
+pub const MAX_DP_SIZE: usize = 20;
+
+// 頂点 0 から出発する巡回路を bit DP で求める。O(2^n n^2)
+// 常に最適解なので、2 つ目の値は常に true
+pub fn solve_dp(distance: &impl DistanceFunction) -> (ArraySolution, bool) {
+    let n = distance.dimension() as usize;
+    assert!(n <= MAX_DP_SIZE, "too large for bit DP: {}", n);
+    if n <= 3 {
+        return (ArraySolution::new(n), true);
+    }
+
+    // 頂点 0 を除いた m 頂点で、dp[mask][last] は 0 から mask の頂点を全て通って last に着く最短路長
+    let m = n - 1;
+    let mut dp = vec![i64::MAX; (1 << m) * m];
+    for last in 0..m {
+        dp[(1 << last) * m + last] = distance.distance(0, last as u32 + 1);
+    }
+    for mask in 1..1usize << m {
+        for last in 0..m {
+            let current = dp[mask * m + last];
+            if current == i64::MAX || mask & (1 << last) == 0 {
+                continue;
+            }
+            for next in 0..m {
+                if mask & (1 << next) != 0 {
+                    continue;
+                }
+                let next_mask = mask | (1 << next);
+                let cost = current + distance.distance(last as u32 + 1, next as u32 + 1);
+                if cost < dp[next_mask * m + next] {
+                    dp[next_mask * m + next] = cost;
+                }
+            }
+        }
+    }
+
+    // 経路の復元
+    let full = (1 << m) - 1;
+    let mut last = (0..m)
+        .min_by_key(|&last| dp[full * m + last] + distance.distance(last as u32 + 1, 0))
+        .unwrap();
+    let mut mask = full;
+    let mut vertex_array = vec![];
+    loop {
+        vertex_array.push(last as u32 + 1);
+        let prev_mask = mask ^ (1 << last);
+        if prev_mask == 0 {
+            break;
+        }
+        let current = dp[mask * m + last];
+        last = (0..m)
+            .find(|&prev| {
+                prev_mask & (1 << prev) != 0
+                    && dp[prev_mask * m + prev] != i64::MAX
+                    && dp[prev_mask * m + prev]
+                        + distance.distance(prev as u32 + 1, last as u32 + 1)
+                        == current
+            })
+            .unwrap();
+        mask = prev_mask;
+    }
+    vertex_array.push(0);
+    vertex_array.reverse();
+    (ArraySolution::from_array(vertex_array), true)
+}
+
+pub struct BranchAndBoundConfig {
+    pub time_ms: u128,
+    // 根で 1-tree 下界を求める劣勾配法の反復回数
+    pub root_iteration: usize,
+    // 子で 1-tree 下界を求める劣勾配法の反復回数。ペナルティは親から引き継ぐ
+    pub child_iteration: usize,
+    pub debug: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EdgeState {
+    Free,
+    Included,
+    Excluded,
+}
+
+// 分枝限定法の 1 ノード。辺ごとに使う・使わないの制約を持つ
+#[derive(Clone)]
+struct Node {
+    state: Vec<EdgeState>,
+    penalty: Vec<f64>,
+}
+
+struct BranchAndBound {
+    n: usize,
+    matrix: Vec<i64>,
+    // 制約のある辺に足すコスト。Included の辺は必ず、Excluded の辺はできる限り 1-tree から外れる
+    big: f64,
+    best_eval: i64,
+    best_tour: Vec<u32>,
+    start: Instant,
+    config: BranchAndBoundConfig,
+    timeout: bool,
+}
+
+impl BranchAndBound {
+    fn cost(&self, node: &Node, id1: usize, id2: usize) -> f64 {
+        let index = id1 * self.n + id2;
+        let shift = match node.state[index] {
+            EdgeState::Free => 0.0,
+            EdgeState::Included => -self.big,
+            EdgeState::Excluded => self.big,
+        };
+        self.matrix[index] as f64 + node.penalty[id1] + node.penalty[id2] + shift
+    }
+
+    // 最小 1-tree の (下界, 辺のリスト) を返す。O(n^2) の Prim 法
+    fn minimum_one_tree(&self, node: &Node) -> (f64, Vec<(usize, usize)>) {
+        let n = self.n;
+        let mut edge_list = Vec::with_capacity(n);
+        let mut sum = 0.0;
+
+        let mut visited = vec![false; n];
+        let mut key = vec![f64::INFINITY; n];
+        let mut parent = vec![0; n];
+        visited[0] = true;
+        let mut current = 1;
+        visited[1] = true;
+        for _iter in 2..n {
+            for next in 2..n {
+                if visited[next] {
+                    continue;
+                }
+                let c = self.cost(node, current, next);
+                if c < key[next] {
+                    key[next] = c;
+                    parent[next] = current;
+                }
+            }
+            let next = (2..n)
+                .filter(|&id| !visited[id])
+                .min_by(|&a, &b| key[a].total_cmp(&key[b]))
+                .unwrap();
+            visited[next] = true;
+            sum += key[next];
+            edge_list.push((parent[next], next));
+            current = next;
+        }
+
+        let mut candidate_list = (1..n)
+            .map(|id| (self.cost(node, 0, id), id))
+            .collect::<Vec<_>>();
+        candidate_list.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for &(c, id) in candidate_list.iter().take(2) {
+            sum += c;
+            edge_list.push((0, id));
+        }
+
+        let no_included = (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .filter(|&(i, j)| node.state[i * n + j] == EdgeState::Included)
+            .count();
+        let bound = sum + no_included as f64 * self.big - 2.0 * node.penalty.iter().sum::<f64>();
+        (bound, edge_list)
+    }
+
+    // 劣勾配法でペナルティを更新しながら下界を上げる
+    // 最良の下界とその時の 1-tree を返す
+    fn ascent(&self, node: &mut Node, no_iteration: usize) -> (f64, Vec<(usize, usize)>) {
+        let mut best = self.minimum_one_tree(node);
+        let mut best_penalty = node.penalty.clone();
+        let mut current = best.clone();
+        let mut step_scale = 2.0;
+        let mut no_stagnation = 0;
+        for _iter in 0..no_iteration {
+            let degree = to_degree(self.n, &current.1);
+            let norm = degree
+                .iter()
+                .map(|&d| ((d - 2) * (d - 2)) as f64)
+                .sum::<f64>();
+            if norm == 0.0 || current.0 >= self.best_eval as f64 {
+                break;
+            }
+            let step = step_scale * (self.best_eval as f64 - current.0).max(1.0) / norm;
+            for (p, &d) in node.penalty.iter_mut().zip(degree.iter()) {
+                *p += step * (d - 2) as f64;
+            }
+            current = self.minimum_one_tree(node);
+            if current.0 > best.0 + 1e-9 {
+                best = current.clone();
+                best_penalty = node.penalty.clone();
+                no_stagnation = 0;
+            } else {
+                no_stagnation += 1;
+                if no_stagnation >= (no_iteration / 20).max(3) {
+                    step_scale /= 2.0;
+                    no_stagnation = 0;
+                }
+            }
+        }
+        node.penalty = best_penalty;
+        best
+    }
+
+    // 辺 (id1, id2) を使うと決める。矛盾すれば false
+    fn include(&self, node: &mut Node, id1: usize, id2: usize) -> bool {
+        let n = self.n;
+        match node.state[id1 * n + id2] {
+            EdgeState::Included => return true,
+            EdgeState::Excluded => return false,
+            EdgeState::Free => {}
+        }
+        node.state[id1 * n + id2] = EdgeState::Included;
+        node.state[id2 * n + id1] = EdgeState::Included;
+
+        for id in [id1, id2] {
+            let degree = (0..n)
+                .filter(|&other| node.state[id * n + other] == EdgeState::Included)
+                .count();
+            if degree > 2 {
+                return false;
+            }
+            if degree == 2 {
+                for other in 0..n {
+                    if other != id && node.state[id * n + other] == EdgeState::Free {
+                        node.state[id * n + other] = EdgeState::Excluded;
+                        node.state[other * n + id] = EdgeState::Excluded;
+                    }
+                }
+            }
+        }
+
+        // 使う辺で繋がったパスが全頂点を通る前に閉じないようにする
+        let (mut prev, mut id, mut length) = (id2, id1, 1);
+        loop {
+            let next = (0..n).find(|&next| {
+                next != prev && next != id && node.state[id * n + next] == EdgeState::Included
+            });
+            match next {
+                Some(next) => {
+                    if next == id2 {
+                        return length + 1 == n;
+                    }
+                    prev = id;
+                    id = next;
+                    length += 1;
+                }
+                None => break,
+            }
+        }
+        true
+    }
+
+    fn exclude(&self, node: &mut Node, id1: usize, id2: usize) -> bool {
+        let n = self.n;
+        if node.state[id1 * n + id2] == EdgeState::Included {
+            return false;
+        }
+        node.state[id1 * n + id2] = EdgeState::Excluded;
+        node.state[id2 * n + id1] = EdgeState::Excluded;
+        true
+    }
+
+    // 1-tree に辺を 1 本加えたときの下界の増分が大きく、最良解を改善できない辺を使わないと決める
+    // 頂点 0 以外の辺は木の経路上の最大の辺と、頂点 0 の辺は 2 番目に安い辺と入れ替えた増分を見る
+    fn eliminate(&self, node: &mut Node, bound: f64, edge_list: &[(usize, usize)]) {
+        let n = self.n;
+        let threshold = self.best_eval as f64 - 1.0 + 1e-6;
+
+        let mut tree = vec![vec![]; n];
+        for &(a, b) in edge_list.iter().filter(|&&(a, b)| a != 0 && b != 0) {
+            let c = self.cost(node, a, b);
+            tree[a].push((b, c));
+            tree[b].push((a, c));
+        }
+        let mut max_cost = vec![f64::MIN; n];
+        let mut stack = vec![];
+        for root in 1..n {
+            // root から木を辿り、各頂点までの経路上の最大コストを求める
+            max_cost[root] = f64::MIN;
+            stack.push((root, usize::MAX));
+            while let Some((id, parent)) = stack.pop() {
+                for &(next, c) in tree[id].iter() {
+                    if next != parent {
+                        max_cost[next] = max_cost[id].max(c);
+                        stack.push((next, id));
+                    }
+                }
+            }
+            for (other, &path_cost) in max_cost.iter().enumerate().skip(root + 1) {
+                if node.state[root * n + other] == EdgeState::Free
+                    && bound + self.cost(node, root, other) - path_cost > threshold
+                {
+                    node.state[root * n + other] = EdgeState::Excluded;
+                    node.state[other * n + root] = EdgeState::Excluded;
+                }
+            }
+        }
+
+        let mut cost_list = (1..n).map(|id| self.cost(node, 0, id)).collect::<Vec<_>>();
+        cost_list.sort_by(|a, b| a.total_cmp(b));
+        let second = cost_list[1];
+        for id in 1..n {
+            if node.state[id] == EdgeState::Free
+                && bound + self.cost(node, 0, id) - second > threshold
+            {
+                node.state[id] = EdgeState::Excluded;
+                node.state[id * n] = EdgeState::Excluded;
+            }
+        }
+    }
+
+    fn search(&mut self, mut node: Node, no_iteration: usize) {
+        if self.start.elapsed().as_millis() > self.config.time_ms {
+            self.timeout = true;
+            return;
+        }
+        let (bound, edge_list) = self.ascent(&mut node, no_iteration);
+        // 距離が整数なので、切り上げが最良解以上なら改善の余地は無い
+        if (bound - 1e-6).ceil() >= self.best_eval as f64 {
+            return;
+        }
+
+        let degree = to_degree(self.n, &edge_list);
+        let Some(v) = (0..self.n)
+            .filter(|&id| degree[id] > 2)
+            .max_by_key(|&id| degree[id])
+        else {
+            // 1-tree が巡回路になっている
+            if let Some(tour) = to_tour(self.n, &edge_list) {
+                let eval = (0..self.n)
+                    .map(|i| {
+                        self.matrix[tour[i] as usize * self.n + tour[(i + 1) % self.n] as usize]
+                    })
+                    .sum::<i64>();
+                if eval < self.best_eval {
+                    if self.config.debug {
+                        eprintln!("improved: {}", eval);
+                    }
+                    self.best_eval = eval;
+                    self.best_tour = tour;
+                }
+            }
+            return;
+        };
+        self.eliminate(&mut node, bound, &edge_list);
+
+        let n = self.n;
+        let free_edge_list = edge_list
+            .iter()
+            .filter_map(|&(a, b)| {
+                if a == v {
+                    Some(b)
+                } else if b == v {
+                    Some(a)
+                } else {
+                    None
+                }
+            })
+            .filter(|&other| node.state[v * n + other] == EdgeState::Free)
+            .collect::<Vec<_>>();
+        let no_included = (0..n)
+            .filter(|&other| node.state[v * n + other] == EdgeState::Included)
+            .count();
+
+        // e1 を使わない / e1 を使い e2 を使わない / e1, e2 を使う の 3 通りに分ける
+        // v で既に 1 本使っているなら、e1 を使わない / e1 を使う の 2 通り
+        let Some(&e1) = free_edge_list.first() else {
+            return;
+        };
+        let mut child_list = vec![];
+        let mut child = node.clone();
+        if self.exclude(&mut child, v, e1) {
+            child_list.push(child);
+        }
+        if no_included == 0 && free_edge_list.len() >= 2 {
+            let e2 = free_edge_list[1];
+            let mut child = node.clone();
+            if self.include(&mut child, v, e1) && self.exclude(&mut child, v, e2) {
+                child_list.push(child);
+            }
+            let mut child = node.clone();
+            if self.include(&mut child, v, e1) && self.include(&mut child, v, e2) {
+                child_list.push(child);
+            }
+        } else {
+            let mut child = node.clone();
+            if self.include(&mut child, v, e1) {
+                child_list.push(child);
+            }
+        }
+
+        for child in child_list.into_iter().rev() {
+            self.search(child, self.config.child_iteration);
+            if self.timeout {
+                return;
+            }
+        }
+    }
+}
+
+fn to_degree(n: usize, edge_list: &[(usize, usize)]) -> Vec<i32> {
+    let mut degree = vec![0; n];
+    for &(a, b) in edge_list.iter() {
+        degree[a] += 1;
+        degree[b] += 1;
+    }
+    degree
+}
+
+fn to_tour(n: usize, edge_list: &[(usize, usize)]) -> Option<Vec<u32>> {
+    let mut adjacent = vec![vec![]; n];
+    for &(a, b) in edge_list.iter() {
+        adjacent[a].push(b);
+        adjacent[b].push(a);
+    }
+    let mut tour = vec![0];
+    let (mut prev, mut id) = (0, *adjacent[0].first()?);
+    while id != 0 {
+        tour.push(id as u32);
+        let next = *adjacent[id].iter().find(|&&next| next != prev)?;
+        prev = id;
+        id = next;
+        if tour.len() > n {
+            return None;
+        }
+    }
+    (tour.len() == n).then_some(tour)
+}
+
+// 1-tree 下界による分枝限定法。initial は上界として使う既知の巡回路で、良い解を渡すほど枝刈りが効く
+// 探索ノード数は 1-tree 下界と最適値の差で決まり、差が 1% 程度なら百頂点程度まで現実的な時間で解ける
+// 時間内に探索し終えれば最適解と true を、そうでなければそれまでの最良解と false を返す
+pub fn solve_branch_and_bound(
+    distance: &impl DistanceFunction,
+    initial: ArraySolution,
+    config: BranchAndBoundConfig,
+) -> (ArraySolution, bool) {
+    assert!(
+        distance.is_symmetric(),
+        "branch and bound requires a symmetric distance; use SymmetricTransform"
+    );
+    let n = distance.dimension() as usize;
+    if n <= 3 {
+        return (initial, true);
+    }
+
+    let matrix = (0..n * n)
+        .map(|index| distance.distance((index / n) as u32, (index % n) as u32))
+        .collect::<Vec<_>>();
+    let max_distance = matrix.iter().map(|d| d.abs()).max().unwrap();
+
+    let mut best_tour = Vec::with_capacity(n);
+    let mut id = 0;
+    for _iter in 0..n {
+        best_tour.push(id);
+        id = initial.next(id);
+    }
+
+    let root_iteration = config.root_iteration;
+    let mut solver = BranchAndBound {
+        n,
+        big: (4 * n as i64 * (max_distance + 1)) as f64,
+        matrix,
+        best_eval: evaluate(distance, &initial),
+        best_tour,
+        start: Instant::now(),
+        config,
+        timeout: false,
+    };
+    let root = Node {
+        state: vec![EdgeState::Free; n * n],
+        penalty: vec![0.0; n],
+    };
+    solver.search(root, root_iteration);
+
+    (ArraySolution::from_array(solver.best_tour), !solver.timeout)
+}
+
+// 小さい問題は bit DP、それ以外は分枝限定法で解く
+pub fn solve(
+    distance: &impl DistanceFunction,
+    initial: ArraySolution,
+    config: BranchAndBoundConfig,
+) -> (ArraySolution, bool) {
+    if distance.dimension() as usize <= MAX_DP_SIZE {
+        solve_dp(distance)
+    } else {
+        solve_branch_and_bound(distance, initial, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        array_solution::ArraySolution,
+        distance::DistanceFunction,
+        euclid_distance::EuclidDistance,
+        evaluate::{evaluate, validate},
+        test_util::random_euclid_distance,
+    };
+
+    use super::{solve_branch_and_bound, solve_dp, BranchAndBoundConfig};
+
+    fn brute_force(distance: &EuclidDistance, n: usize) -> i64 {
+        fn dfs(distance: &EuclidDistance, path: &mut Vec<u32>, used: &mut [bool]) -> i64 {
+            let n = used.len();
+            let last = *path.last().unwrap();
+            if path.len() == n {
+                return distance.distance(last, 0);
+            }
+            let mut best = i64::MAX;
+            for next in 1..n as u32 {
+                if used[next as usize] {
+                    continue;
+                }
+                used[next as usize] = true;
+                path.push(next);
+                best = best.min(distance.distance(last, next) + dfs(distance, path, used));
+                path.pop();
+                used[next as usize] = false;
+            }
+            best
+        }
+        let mut used = vec![false; n];
+        used[0] = true;
+        dfs(distance, &mut vec![0], &mut used)
+    }
+
+    #[test]
+    fn test_solve_dp() {
+        const SIZE: usize = 8;
+        let distance = random_euclid_distance(SIZE);
+        let (solution, optimal) = solve_dp(&distance);
+        assert!(optimal);
+        assert!(validate(&distance, &solution));
+        assert_eq!(evaluate(&distance, &solution), brute_force(&distance, SIZE));
+    }
+
+    #[test]
+    fn test_solve_branch_and_bound() {
+        const SIZE: usize = 14;
+        let distance = random_euclid_distance(SIZE);
+        let (expected, _) = solve_dp(&distance);
+        let (solution, optimal) = solve_branch_and_bound(
+            &distance,
+            ArraySolution::new(SIZE),
+            BranchAndBoundConfig {
+                time_ms: 60_000,
+                root_iteration: 100,
+                child_iteration: 20,
+                debug: false,
+            },
+        );
+        assert!(optimal);
+        assert!(validate(&distance, &solution));
+        assert_eq!(
+            evaluate(&distance, &solution),
+            evaluate(&distance, &expected)
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    array_solution::ArraySolution, distance::DistanceFunction, evaluate::evaluate,
+    solution::Solution, union_find::UnionFind,
+};
+
+fn contains_edge(solution: &impl Solution, id1: u32, id2: u32) -> bool {
+    solution.next(id1) == id2 || solution.prev(id1) == id2
+}
+
+// Generalized Partition Crossover
+// 2 つの巡回路で異なる辺だけを残したグラフの連結成分ごとに、どちらの親の経路を使うかを独立に選ぶ
+// 共通辺 2 本だけで外部と繋がっている成分は、どちらの親も 1 本のパスとして通過するので、
+// 安い方の親のパスに入れ替えても巡回路が保たれる
+// 子は良い方の親をベースにして作るので、両方の親以上に良い解になる
+pub fn crossover(
+    distance: &impl DistanceFunction,
+    parent1: &impl Solution,
+    parent2: &impl Solution,
+) -> ArraySolution {
+    let n = parent1.len();
+    assert_eq!(n, parent2.len());
+
+    if evaluate(distance, parent1) <= evaluate(distance, parent2) {
+        crossover_inner(distance, parent1, parent2)
+    } else {
+        crossover_inner(distance, parent2, parent1)
+    }
+}
+
+fn crossover_inner(
+    distance: &impl DistanceFunction,
+    base: &impl Solution,
+    other: &impl Solution,
+) -> ArraySolution {
+    let n = base.len();
+
+    // 片方にしかない辺で連結成分を作る
+    let mut union_find = UnionFind::new(n);
+    for id in 0..n as u32 {
+        let base_next = base.next(id);
+        if !contains_edge(other, id, base_next) {
+            union_find.unite(id, base_next);
+        }
+        let other_next = other.next(id);
+        if !contains_edge(base, id, other_next) {
+            union_find.unite(id, other_next);
+        }
+    }
+
+    // 成分ごとに、外部と繋がる共通辺の数と、base の経路と other の経路のコスト差を集計
+    let mut cut_count = vec![0; n];
+    let mut cost_diff = vec![0i64; n];
+    for id in 0..n as u32 {
+        let base_next = base.next(id);
+        if contains_edge(other, id, base_next) {
+            let root1 = union_find.find(id);
+            let root2 = union_find.find(base_next);
+            if root1 != root2 {
+                cut_count[root1 as usize] += 1;
+                cut_count[root2 as usize] += 1;
+            }
+        } else {
+            let root = union_find.find(id);
+            cost_diff[root as usize] += distance.distance(id, base_next);
+        }
+
+        let other_next = other.next(id);
+        if !contains_edge(base, id, other_next) {
+            let root = union_find.find(id);
+            cost_diff[root as usize] -= distance.distance(id, other_next);
+        }
+    }
+
+    let use_other = (0..n)
+        .map(|root| cut_count[root] == 2 && cost_diff[root] > 0)
+        .collect::<Vec<_>>();
+
+    // 子の隣接リストを作る
+    let mut adjacent = vec![[u32::MAX; 2]; n];
+    let mut add_edge = |id1: u32, id2: u32| {
+        for (from, to) in [(id1, id2), (id2, id1)] {
+            let slot = &mut adjacent[from as usize];
+            if slot[0] == u32::MAX {
+                slot[0] = to;
+            } else {
+                assert_eq!(slot[1], u32::MAX);
+                slot[1] = to;
+            }
+        }
+    };
+    for id in 0..n as u32 {
+        let base_next = base.next(id);
+        let root = union_find.find(id) as usize;
+        if contains_edge(other, id, base_next) || !use_other[root] {
+            add_edge(id, base_next);
+        }
+        let other_next = other.next(id);
+        if !contains_edge(base, id, other_next) && use_other[root] {
+            add_edge(id, other_next);
+        }
+    }
+
+    let mut content = Vec::with_capacity(n);
+    let mut prev = adjacent[0][1];
+    let mut id = 0;
+    for _iter in 0..n {
+        content.push(id);
+        let next = if adjacent[id as usize][0] == prev {
+            adjacent[id as usize][1]
+        } else {
+            adjacent[id as usize][0]
+        };
+        prev = id;
+        id = next;
+    }
+    assert_eq!(id, 0);
+    ArraySolution::from_array(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::{seq::SliceRandom, thread_rng};
+
+    use crate::{
+        activation_order::ActivationOrder,
+        array_solution::ArraySolution,
+        evaluate::{evaluate, validate},
+        improvement_strategy::ImprovementStrategy,
+        opt2::{self, Opt2Config},
+        test_util::random_euclid_distance,
+    };
+
+    use super::crossover;
+
+    #[test]
+    fn test_crossover() {
+        const SIZE: usize = 200;
+        let mut rng = thread_rng();
+
+        let distance = random_euclid_distance(SIZE);
+
+        let mut local_optimum = || {
+            let mut content = (0..SIZE as u32).collect::<Vec<_>>();
+            content.shuffle(&mut rng);
+            let config = Opt2Config {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: None,
+                max_iteration: None,
+                improvement_strategy: ImprovementStrategy::FirstImprovement,
+                activation_order: ActivationOrder::Queue,
+            };
+            opt2::solve(&distance, ArraySolution::from_array(content), config).0
+        };
+
+        for _iter in 0..10 {
+            let parent1 = local_optimum();
+            let parent2 = local_optimum();
+            let child = crossover(&distance, &parent1, &parent2);
+
+            // 巡回路になっている
+            assert!(validate(&distance, &child));
+
+            let child_eval = evaluate(&distance, &child);
+            assert!(child_eval <= evaluate(&distance, &parent1));
+            assert!(child_eval <= evaluate(&distance, &parent2));
+
+            // 同じ親同士なら変化しない
+            let same = crossover(&distance, &parent1, &parent1);
+            assert_eq!(evaluate(&distance, &same), evaluate(&distance, &parent1));
+        }
+    }
+}
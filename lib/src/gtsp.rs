@@ -0,0 +1,333 @@
+use std::path::PathBuf;
+
+use crate::{
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    euclid_distance::read_tsplib_section,
+    lkh::{self, LKHConfig},
+    solution::Solution,
+};
+
+// クラスタの数がこれ以下なら、どの順番でも同じ巡回路になるので LKH を使わない
+const MAX_TRIVIAL_CLUSTER_COUNT: usize = 3;
+
+// 頂点をクラスタに分けた問題。巡回路は各クラスタからちょうど 1 頂点ずつ訪れる
+pub struct Gtsp {
+    pub cluster_list: Vec<Vec<u32>>,
+}
+
+impl Gtsp {
+    pub fn new(cluster_list: Vec<Vec<u32>>) -> Gtsp {
+        assert!(!cluster_list.is_empty(), "no cluster");
+        assert!(cluster_list.iter().all(|cluster| !cluster.is_empty()));
+        let mut id_list = cluster_list.iter().flatten().collect::<Vec<_>>();
+        id_list.sort();
+        id_list.dedup();
+        assert_eq!(
+            id_list.len(),
+            cluster_list
+                .iter()
+                .map(|cluster| cluster.len())
+                .sum::<usize>(),
+            "a vertex belongs to more than one cluster"
+        );
+        Gtsp { cluster_list }
+    }
+
+    // 各クラスタからちょうど 1 頂点ずつ訪れる巡回路か
+    pub fn validate(&self, tour: &[u32]) -> bool {
+        tour.len() == self.cluster_list.len()
+            && self
+                .cluster_list
+                .iter()
+                .all(|cluster| tour.iter().filter(|id| cluster.contains(id)).count() == 1)
+    }
+}
+
+// GTSP-LIB 形式の GTSP_SET_SECTION を読む。各行は「クラスタ番号 頂点番号... -1」で、頂点番号は 0 始まりに直す
+// 座標は EuclidDistance::load_tsplib で読む
+pub fn load_tsplib(filepath: &PathBuf) -> Gtsp {
+    let cluster_list = read_tsplib_section(filepath, "GTSP_SET_SECTION")
+        .into_iter()
+        .map(|row| {
+            row[1..]
+                .iter()
+                .take_while(|&&id| id != -1)
+                .map(|&id| (id - 1) as u32)
+                .collect()
+        })
+        .collect();
+    Gtsp::new(cluster_list)
+}
+
+// 巡回路の長さ
+pub fn evaluate_tour(distance: &impl DistanceFunction, tour: &[u32]) -> i64 {
+    tour.iter()
+        .zip(tour.iter().cycle().skip(1))
+        .map(|(&from, &to)| distance.distance(from, to))
+        .sum()
+}
+
+// クラスタ i を、代表として選んだ頂点 representative_list[i] とみなした問題
+struct ClusterDistance<'a, T: DistanceFunction> {
+    ref_distance: &'a T,
+    representative_list: Vec<u32>,
+}
+
+impl<'a, T: DistanceFunction> DistanceFunction for ClusterDistance<'a, T> {
+    fn distance(&self, id1: u32, id2: u32) -> i64 {
+        self.ref_distance.distance(
+            self.representative_list[id1 as usize],
+            self.representative_list[id2 as usize],
+        )
+    }
+
+    fn dimension(&self) -> u32 {
+        self.representative_list.len() as u32
+    }
+
+    fn name(&self) -> String {
+        self.ref_distance.name()
+    }
+
+    fn is_symmetric(&self) -> bool {
+        self.ref_distance.is_symmetric()
+    }
+
+    fn coordinate(&self, id: u32) -> Option<(i64, i64)> {
+        self.ref_distance
+            .coordinate(self.representative_list[id as usize])
+    }
+}
+
+// クラスタを訪れる順番を固定したとき、各クラスタから選ぶ頂点を最適にする (cluster optimization)
+// 最も小さいクラスタの頂点それぞれを始点として、クラスタを層とするグラフの最短路を DP で求める
+pub fn optimize_cluster(
+    distance: &impl DistanceFunction,
+    gtsp: &Gtsp,
+    cluster_order: &[usize],
+) -> Vec<u32> {
+    let m = cluster_order.len();
+    let first = (0..m)
+        .min_by_key(|&index| gtsp.cluster_list[cluster_order[index]].len())
+        .unwrap();
+    let layer_list = (0..m)
+        .map(|index| &gtsp.cluster_list[cluster_order[(first + index) % m]])
+        .collect::<Vec<_>>();
+
+    let mut best: Option<(i64, Vec<u32>)> = None;
+    for &start in layer_list[0] {
+        // cost[k][v] は start から層 k の v 番目の頂点までの最短距離、parent はその直前の頂点の番号
+        let mut cost = vec![vec![0]];
+        let mut parent: Vec<Vec<usize>> = vec![vec![0]];
+        let mut prev_layer = vec![start];
+        for layer in layer_list.iter().skip(1) {
+            let prev_cost = cost.last().unwrap();
+            let (layer_cost, layer_parent): (Vec<_>, Vec<_>) = layer
+                .iter()
+                .map(|&id| {
+                    prev_layer
+                        .iter()
+                        .enumerate()
+                        .map(|(index, &prev)| {
+                            (prev_cost[index] + distance.distance(prev, id), index)
+                        })
+                        .min()
+                        .unwrap()
+                })
+                .unzip();
+            cost.push(layer_cost);
+            parent.push(layer_parent);
+            prev_layer = layer.to_vec();
+        }
+
+        let (length, mut index) = prev_layer
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| (cost[m - 1][index] + distance.distance(id, start), index))
+            .min()
+            .unwrap();
+        if best.as_ref().is_some_and(|best| best.0 <= length) {
+            continue;
+        }
+        let mut tour = vec![0; m];
+        for k in (1..m).rev() {
+            tour[k] = layer_list[k][index];
+            index = parent[k][index];
+        }
+        tour[0] = start;
+        best = Some((length, tour));
+    }
+    best.unwrap().1
+}
+
+// LKH でクラスタの順番を求め、cluster optimization で各クラスタの頂点を選び直すことを、改善しなくなるか max_round 回まで繰り返す
+// 返り値は各クラスタから 1 頂点ずつ選んだ巡回路の頂点列
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    gtsp: &Gtsp,
+    max_round: usize,
+    config: LKHConfig,
+) -> Vec<u32> {
+    let m = gtsp.cluster_list.len();
+    let mut cluster_order = (0..m).collect::<Vec<_>>();
+    let mut tour = optimize_cluster(distance, gtsp, &cluster_order);
+    if m <= MAX_TRIVIAL_CLUSTER_COUNT {
+        return tour;
+    }
+    let mut best_length = evaluate_tour(distance, &tour);
+
+    let mut cluster_of = vec![usize::MAX; distance.dimension() as usize];
+    for (cluster, id_list) in gtsp.cluster_list.iter().enumerate() {
+        for &id in id_list {
+            cluster_of[id as usize] = cluster;
+        }
+    }
+
+    // 代表の頂点は round ごとに変わるので、近傍リストのキャッシュは使わない
+    let config = LKHConfig {
+        use_neighbor_cache: false,
+        ..config
+    };
+    for round in 0..max_round {
+        // 現在の巡回路の順番を初期解として、代表の頂点の間の TSP を解く
+        cluster_order = tour.iter().map(|&id| cluster_of[id as usize]).collect();
+        let mut representative_list = vec![0; m];
+        for (&cluster, &id) in cluster_order.iter().zip(tour.iter()) {
+            representative_list[cluster] = id;
+        }
+        let cluster_distance = ClusterDistance {
+            ref_distance: distance,
+            representative_list,
+        };
+        let solution = lkh::solve(
+            &cluster_distance,
+            ArraySolution::from_array(cluster_order.iter().map(|&c| c as u32).collect()),
+            config.clone(),
+        );
+        let mut id = 0;
+        for cluster in cluster_order.iter_mut() {
+            *cluster = id as usize;
+            id = solution.next(id);
+        }
+
+        let new_tour = optimize_cluster(distance, gtsp, &cluster_order);
+        let length = evaluate_tour(distance, &new_tour);
+        if config.debug {
+            eprintln!("round = {}, length = {}", round, length);
+        }
+        if length >= best_length {
+            break;
+        }
+        best_length = length;
+        tour = new_tour;
+    }
+    tour
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::PathBuf};
+
+    use rand::{seq::SliceRandom, thread_rng};
+
+    use crate::{
+        activation_order::ActivationOrder,
+        distance::DistanceFunction,
+        euclid_distance::EuclidDistance,
+        improvement_strategy::ImprovementStrategy,
+        lkh::LKHConfig,
+        test_util::{random_euclid_distance, temp_filepath},
+    };
+
+    use super::{evaluate_tour, load_tsplib, optimize_cluster, solve, Gtsp};
+
+    fn random_instance(no_cluster: usize, cluster_size: usize) -> (EuclidDistance, Gtsp) {
+        let mut rng = thread_rng();
+        let size = no_cluster * cluster_size;
+        let distance = random_euclid_distance(size);
+
+        let mut id_list = (0..size as u32).collect::<Vec<_>>();
+        id_list.shuffle(&mut rng);
+        let cluster_list = id_list
+            .chunks(cluster_size)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        (distance, Gtsp::new(cluster_list))
+    }
+
+    #[test]
+    fn test_load_tsplib() {
+        let filepath = temp_filepath("gtsp_test.tsp");
+        let mut f = std::fs::File::create(&filepath).unwrap();
+        writeln!(
+            f,
+            "NAME: test\nTYPE: GTSP\nDIMENSION: 5\nGTSP_SETS: 2\nEDGE_WEIGHT_TYPE: EUC_2D\n\
+             NODE_COORD_SECTION\n1 0 0\n2 0 1\n3 1 1\n4 1 0\n5 2 2\n\
+             GTSP_SET_SECTION:\n1 1 3 -1\n2 2 4 5 -1\nEOF"
+        )
+        .unwrap();
+        drop(f);
+
+        let gtsp = load_tsplib(&filepath);
+        assert_eq!(gtsp.cluster_list, vec![vec![0, 2], vec![1, 3, 4]]);
+        assert_eq!(EuclidDistance::load_tsplib(&filepath).dimension(), 5);
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn test_optimize_cluster() {
+        const NO_CLUSTER: usize = 6;
+        const CLUSTER_SIZE: usize = 3;
+        let (distance, gtsp) = random_instance(NO_CLUSTER, CLUSTER_SIZE);
+        let cluster_order = (0..NO_CLUSTER).collect::<Vec<_>>();
+
+        let tour = optimize_cluster(&distance, &gtsp, &cluster_order);
+        assert!(gtsp.validate(&tour));
+
+        // 各クラスタから選ぶ頂点を全通り試す
+        let mut best_length = i64::MAX;
+        for mut code in 0..CLUSTER_SIZE.pow(NO_CLUSTER as u32) {
+            let mut candidate = vec![];
+            for cluster in cluster_order.iter() {
+                candidate.push(gtsp.cluster_list[*cluster][code % CLUSTER_SIZE]);
+                code /= CLUSTER_SIZE;
+            }
+            best_length = best_length.min(evaluate_tour(&distance, &candidate));
+        }
+        assert_eq!(evaluate_tour(&distance, &tour), best_length);
+    }
+
+    #[test]
+    fn test_solve() {
+        const NO_CLUSTER: usize = 40;
+        const CLUSTER_SIZE: usize = 5;
+        let (distance, gtsp) = random_instance(NO_CLUSTER, CLUSTER_SIZE);
+
+        let initial_length = evaluate_tour(
+            &distance,
+            &optimize_cluster(&distance, &gtsp, &(0..NO_CLUSTER).collect::<Vec<_>>()),
+        );
+        let tour = solve(
+            &distance,
+            &gtsp,
+            10,
+            LKHConfig {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: 200,
+                start_kick_step: 5,
+                kick_step_diff: 5,
+                end_kick_step: 10,
+                fail_count_threashold: 10,
+                max_depth: 5,
+                use_non_sequential_move: true,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+            },
+        );
+        assert!(gtsp.validate(&tour));
+        assert!(evaluate_tour(&distance, &tour) < initial_length);
+    }
+}
@@ -0,0 +1,353 @@
+use std::time::Instant;
+
+use crate::{distance::DistanceFunction, neighbor_table::NeighborTable, union_find::UnionFind};
+
+pub struct HeldKarpConfig {
+    // 1-tree を作る候補グラフの、各頂点の近傍数
+    pub neighbor_size: usize,
+    pub max_iteration: usize,
+    pub time_ms: u128,
+    pub debug: bool,
+}
+
+struct CandidateGraph {
+    // 各頂点の候補と、その距離
+    adjacent: Vec<Vec<(u32, i64)>>,
+    // 各頂点の候補の中で最も遠い距離
+    radius: Vec<i64>,
+    // 頂点 0 以外の頂点を radius の昇順に並べたもの
+    order: Vec<u32>,
+}
+
+// 頂点 0 を除いた最小全域木に頂点 0 から最も安い 2 辺を加えた最小 1-tree を作る
+// 辺 (i, j) のコストは d(i, j) + penalty[i] + penalty[j]
+// 候補グラフに無い辺は、距離を max(radius[i], radius[j]) に置き換えて全ペアに張る
+// 置き換えた距離は元の距離以下になるので、1-tree のコストは最適巡回路長の下界になる
+// 全ペアの辺は陽に作らず、order の累積最小値から各成分の最も安い辺を求める Borůvka 法で作る
+// 1-tree のコストと各頂点の次数を返す
+fn minimum_one_tree(
+    distance: &impl DistanceFunction,
+    graph: &CandidateGraph,
+    penalty: &[f64],
+    degree: &mut [i32],
+) -> f64 {
+    let n = graph.adjacent.len();
+    let (radius, order) = (&graph.radius, &graph.order);
+    // 同じ辺はどちらの向きから計算しても同じ値になるようにし、閉路を作らない
+    let cost = |id1: u32, id2: u32, d: i64| {
+        let (id1, id2) = (id1.min(id2), id1.max(id2));
+        d as f64 + penalty[id1 as usize] + penalty[id2 as usize]
+    };
+    let radius_cost =
+        |id1: u32, id2: u32| cost(id1, id2, radius[id1 as usize].max(radius[id2 as usize]));
+
+    degree.iter_mut().for_each(|d| *d = 0);
+    let mut sum = 0.0;
+
+    let mut union_find = UnionFind::new(n);
+    let mut no_component = n - 1;
+    while no_component > 1 {
+        let root = (0..n as u32)
+            .map(|id| union_find.find(id))
+            .collect::<Vec<_>>();
+        // 成分ごとの、外へ出る最も安い辺
+        let mut best = vec![(f64::INFINITY, u32::MAX, u32::MAX); n];
+        let mut update = |c: f64, id1: u32, id2: u32| {
+            let best = &mut best[root[id1 as usize] as usize];
+            if c < best.0 {
+                *best = (c, id1, id2);
+            }
+        };
+
+        for id1 in 1..n as u32 {
+            for &(id2, d) in graph.adjacent[id1 as usize].iter() {
+                if id2 != 0 && root[id1 as usize] != root[id2 as usize] {
+                    update(cost(id1, id2, d), id1, id2);
+                }
+            }
+        }
+
+        // order の前にある頂点 j との辺の距離は radius[i]、後ろにある頂点との辺の距離は radius[j]
+        // 前からは penalty[j]、後ろからは radius[j] + penalty[j] の最小値を、異なる成分の 2 つまで持つ
+        for reverse in [false, true] {
+            let key = |id: u32| {
+                if reverse {
+                    radius[id as usize] as f64 + penalty[id as usize]
+                } else {
+                    penalty[id as usize]
+                }
+            };
+            let mut top = [(f64::INFINITY, u32::MAX); 2];
+            for index in 0..order.len() {
+                let id = if reverse {
+                    order[order.len() - 1 - index]
+                } else {
+                    order[index]
+                };
+                let r = root[id as usize];
+                if let Some(&(_, other)) = top
+                    .iter()
+                    .find(|&&(_, other)| other != u32::MAX && root[other as usize] != r)
+                {
+                    update(radius_cost(id, other), id, other);
+                }
+
+                let item = (key(id), id);
+                if top[0].1 != u32::MAX && root[top[0].1 as usize] == r {
+                    if item.0 < top[0].0 {
+                        top[0] = item;
+                    }
+                } else if item.0 < top[0].0 {
+                    top[1] = top[0];
+                    top[0] = item;
+                } else if top[1].1 != u32::MAX && root[top[1].1 as usize] == r {
+                    if item.0 < top[1].0 {
+                        top[1] = item;
+                    }
+                } else if item.0 < top[1].0 {
+                    top[1] = item;
+                }
+            }
+        }
+
+        // 同じコストの辺で閉路ができたときは、その辺を飛ばす
+        for &(c, id1, id2) in best.iter() {
+            if id1 == u32::MAX || union_find.find(id1) == union_find.find(id2) {
+                continue;
+            }
+            union_find.unite(id1, id2);
+            no_component -= 1;
+            sum += c;
+            degree[id1 as usize] += 1;
+            degree[id2 as usize] += 1;
+        }
+    }
+
+    // 頂点 0 は全頂点との辺から安い 2 本を選ぶ
+    let mut first = (f64::INFINITY, 0);
+    let mut second = (f64::INFINITY, 0);
+    for id in 1..n as u32 {
+        let c = cost(0, id, distance.distance(0, id));
+        if c < first.0 {
+            second = first;
+            first = (c, id);
+        } else if c < second.0 {
+            second = (c, id);
+        }
+    }
+    for (c, id) in [first, second] {
+        sum += c;
+        degree[0] += 1;
+        degree[id as usize] += 1;
+    }
+    sum - 2.0 * penalty.iter().sum::<f64>()
+}
+
+// 近傍による候補グラフを作る
+fn candidate_graph(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    neighbor_size: usize,
+) -> CandidateGraph {
+    let n = distance.dimension() as usize;
+    let neighbor_table = NeighborTable::build(distance, neighbor_size);
+    // 近傍表は距離の近い順に選ぶので、候補に無い頂点までの距離は radius 以上になる
+    let radius = (0..n as u32)
+        .map(|id| {
+            neighbor_table
+                .neighbor_list(id)
+                .iter()
+                .map(|&next| distance.distance(id, next))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let mut order = (1..n as u32).collect::<Vec<_>>();
+    order.sort_by_key(|&id| radius[id as usize]);
+    let mut graph = vec![vec![]; n];
+    for id1 in 0..n as u32 {
+        for &id2 in neighbor_table.neighbor_list(id1).iter() {
+            let d = distance.distance(id1, id2);
+            graph[id1 as usize].push((id2, d));
+            graph[id2 as usize].push((id1, d));
+        }
+    }
+    for list in graph.iter_mut() {
+        list.sort();
+        list.dedup();
+    }
+    CandidateGraph {
+        adjacent: graph,
+        radius,
+        order,
+    }
+}
+
+// Held-Karp 下界を、頂点ペナルティの劣勾配法で求める
+// upper_bound は既知の巡回路長で、劣勾配法の歩幅の決定に使う
+// 1-tree は近傍による疎な候補グラフと radius から作るので、頂点数が多くても各反復は O(n log n) 程度で済む
+pub fn lower_bound(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    upper_bound: i64,
+    config: HeldKarpConfig,
+) -> i64 {
+    assert!(
+        distance.is_symmetric(),
+        "1-tree bound requires a symmetric distance; use SymmetricTransform"
+    );
+    let start = Instant::now();
+    let n = distance.dimension() as usize;
+    if n <= 2 {
+        return upper_bound;
+    }
+
+    let graph = candidate_graph(distance, config.neighbor_size);
+
+    let mut penalty = vec![0.0; n];
+    let mut degree = vec![0; n];
+    let mut best_bound = f64::MIN;
+    // 歩幅の係数。一定回数改善しなければ半分にする
+    let mut step_scale = 2.0;
+    let mut no_stagnation = 0;
+
+    for iter in 0..config.max_iteration {
+        if start.elapsed().as_millis() > config.time_ms || step_scale < 1e-6 {
+            break;
+        }
+        let bound = minimum_one_tree(distance, &graph, &penalty, &mut degree);
+        if bound > best_bound + 1e-9 {
+            best_bound = bound;
+            no_stagnation = 0;
+        } else {
+            no_stagnation += 1;
+            if no_stagnation >= 30 {
+                step_scale /= 2.0;
+                no_stagnation = 0;
+            }
+        }
+        if config.debug && iter % 100 == 0 {
+            eprintln!("iter {}: bound = {:.1}", iter, best_bound);
+        }
+
+        let norm = degree
+            .iter()
+            .map(|&d| ((d - 2) * (d - 2)) as f64)
+            .sum::<f64>();
+        if norm == 0.0 {
+            // 1-tree が巡回路になっているので、これ以上は上がらない
+            break;
+        }
+        let step = step_scale * (upper_bound as f64 - bound).max(1.0) / norm;
+        for (p, &d) in penalty.iter_mut().zip(degree.iter()) {
+            *p += step * (d - 2) as f64;
+        }
+    }
+
+    // 距離が整数なので、最適値は下界の切り上げ以上
+    ((best_bound - 1e-6).ceil() as i64).min(upper_bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        euclid_distance::EuclidDistance, evaluate::evaluate, exact::solve_dp,
+        test_util::random_euclid_distance,
+    };
+
+    use rand::{thread_rng, Rng};
+
+    use crate::distance::DistanceFunction;
+
+    use super::{candidate_graph, lower_bound, minimum_one_tree, HeldKarpConfig};
+
+    // 完全グラフ上の最小 1-tree のコスト
+    fn complete_one_tree(distance: &impl DistanceFunction, penalty: &[f64]) -> f64 {
+        let n = penalty.len();
+        let cost = |id1: usize, id2: usize| {
+            distance.distance(id1 as u32, id2 as u32) as f64 + penalty[id1] + penalty[id2]
+        };
+        let mut sum = 0.0;
+        let mut visited = vec![false; n];
+        let mut key = vec![f64::INFINITY; n];
+        key[1] = 0.0;
+        for _iter in 1..n {
+            let id = (1..n)
+                .filter(|&id| !visited[id])
+                .min_by(|&a, &b| key[a].total_cmp(&key[b]))
+                .unwrap();
+            visited[id] = true;
+            sum += key[id];
+            for (next, k) in key.iter_mut().enumerate().skip(1) {
+                *k = k.min(cost(id, next));
+            }
+        }
+        let mut cost_list = (1..n).map(|id| cost(0, id)).collect::<Vec<_>>();
+        cost_list.sort_by(|a, b| a.total_cmp(b));
+        sum + cost_list[0] + cost_list[1] - 2.0 * penalty.iter().sum::<f64>()
+    }
+
+    #[test]
+    fn test_minimum_one_tree() {
+        const SIZE: usize = 60;
+        let mut rng = thread_rng();
+        for neighbor_size in [1, 3, SIZE] {
+            let distance = random_euclid_distance(SIZE);
+            let graph = candidate_graph(&distance, neighbor_size);
+            let penalty = (0..SIZE)
+                .map(|_| rng.gen_range(-50.0..50.0))
+                .collect::<Vec<f64>>();
+            let mut degree = vec![0; SIZE];
+            let bound = minimum_one_tree(&distance, &graph, &penalty, &mut degree);
+            let complete = complete_one_tree(&distance, &penalty);
+            assert!(bound <= complete + 1e-6);
+            if neighbor_size == SIZE {
+                // 候補グラフが完全グラフなら一致する
+                assert!((bound - complete).abs() < 1e-6);
+            }
+            assert_eq!(degree.iter().sum::<i32>(), 2 * SIZE as i32);
+        }
+    }
+
+    #[test]
+    fn test_lower_bound_with_sparse_graph() {
+        const SIZE: usize = 10;
+        for _iter in 0..10 {
+            let distance = random_euclid_distance(SIZE);
+            let (solution, _) = solve_dp(&distance);
+            let optimal = evaluate(&distance, &solution);
+
+            // 近傍 1 つの候補グラフ上の 1-tree は、完全グラフ上の最小 1-tree より重くなりやすい
+            let bound = lower_bound(
+                &distance,
+                2 * optimal,
+                HeldKarpConfig {
+                    neighbor_size: 1,
+                    max_iteration: 300,
+                    time_ms: 10_000,
+                    debug: false,
+                },
+            );
+            assert!(bound <= optimal);
+        }
+    }
+
+    #[test]
+    fn test_lower_bound() {
+        let distance = EuclidDistance::load_tsplib(&PathBuf::from("../dataset/xqf131.tsp"));
+        const OPTIMAL: i64 = 564;
+
+        let bound = lower_bound(
+            &distance,
+            OPTIMAL,
+            HeldKarpConfig {
+                neighbor_size: 10,
+                max_iteration: 1000,
+                time_ms: 10_000,
+                debug: false,
+            },
+        );
+        assert!(bound <= OPTIMAL);
+        assert!(bound as f64 >= 0.97 * OPTIMAL as f64);
+    }
+}
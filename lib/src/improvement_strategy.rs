@@ -0,0 +1,93 @@
+// 改善近傍のうちどれを採用するか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImprovementStrategy {
+    // 最初に見つかった改善を採用する
+    FirstImprovement,
+    // 全候補を調べて最良の改善を採用する
+    BestImprovement,
+    // 最初に見つかった k 個の改善のうち最良のものを採用する
+    BestOfFirst(usize),
+}
+
+impl ImprovementStrategy {
+    // no_improvement 個の改善が見つかった時点で探索を打ち切ってよいか
+    pub fn is_satisfied(&self, no_improvement: usize) -> bool {
+        match *self {
+            ImprovementStrategy::FirstImprovement => no_improvement >= 1,
+            ImprovementStrategy::BestImprovement => false,
+            ImprovementStrategy::BestOfFirst(k) => no_improvement >= k.max(1),
+        }
+    }
+}
+
+// 改善近傍を順に受け取り、戦略に従って採用する近傍を保持する
+pub struct ImprovementSelector<T> {
+    strategy: ImprovementStrategy,
+    no_improvement: usize,
+    best_gain: i64,
+    best: Option<T>,
+}
+
+impl<T> ImprovementSelector<T> {
+    pub fn new(strategy: ImprovementStrategy) -> ImprovementSelector<T> {
+        ImprovementSelector {
+            strategy,
+            no_improvement: 0,
+            best_gain: 0,
+            best: None,
+        }
+    }
+
+    // 候補を登録して、探索を打ち切ってよければ true を返す
+    pub fn update(&mut self, gain: i64, candidate: T) -> bool {
+        if gain > 0 {
+            self.no_improvement += 1;
+            if gain > self.best_gain {
+                self.best_gain = gain;
+                self.best = Some(candidate);
+            }
+        }
+        self.is_satisfied()
+    }
+
+    pub fn is_satisfied(&self) -> bool {
+        self.strategy.is_satisfied(self.no_improvement)
+    }
+
+    pub fn best_gain(&self) -> i64 {
+        self.best_gain
+    }
+
+    pub fn into_best(self) -> Option<(i64, T)> {
+        let best_gain = self.best_gain;
+        self.best.map(|best| (best_gain, best))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImprovementSelector, ImprovementStrategy};
+
+    #[test]
+    fn test_improvement_selector() {
+        let gain_list = [0, 3, -1, 5, 2, 8];
+
+        let select = |strategy| {
+            let mut selector = ImprovementSelector::new(strategy);
+            for (index, gain) in gain_list.iter().enumerate() {
+                if selector.update(*gain, index) {
+                    break;
+                }
+            }
+            selector.into_best()
+        };
+
+        assert_eq!(select(ImprovementStrategy::FirstImprovement), Some((3, 1)));
+        assert_eq!(select(ImprovementStrategy::BestImprovement), Some((8, 5)));
+        assert_eq!(select(ImprovementStrategy::BestOfFirst(3)), Some((5, 3)));
+        assert_eq!(
+            ImprovementSelector::<usize>::new(ImprovementStrategy::BestImprovement).into_best(),
+            None
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use rand::{rngs::ThreadRng, Rng};
 
 pub struct IntSet {
@@ -7,7 +9,7 @@ pub struct IntSet {
 }
 
 impl IntSet {
-    const NONE: u32 = std::u32::MAX;
+    const NONE: u32 = u32::MAX;
 
     pub fn new(n: usize) -> IntSet {
         IntSet {
@@ -40,6 +42,7 @@ impl IntSet {
         self.index += 1;
     }
 
+    #[allow(dead_code)]
     pub fn pop(&mut self) -> Option<u32> {
         if self.is_empty() {
             None
@@ -77,3 +80,132 @@ impl IntSet {
         self.array.len()
     }
 }
+
+// 追加された順に取り出す IntSet
+// 途中の要素の remove は印を付けるだけにして、先頭に来た時点で読み飛ばす
+// 消した要素を再度追加したときに古い位置で取り出さないよう、各要素は追加した時の通し番号を持つ
+pub struct IntQueue {
+    // (要素, 追加した時の通し番号)
+    queue: VecDeque<(u32, usize)>,
+    // 含まれている要素の最後に追加した時の通し番号
+    order_of: Vec<usize>,
+    no_pushed: usize,
+    len: usize,
+}
+
+impl IntQueue {
+    const NONE: usize = usize::MAX;
+
+    pub fn new(n: usize) -> IntQueue {
+        IntQueue {
+            queue: VecDeque::with_capacity(n),
+            order_of: vec![Self::NONE; n],
+            no_pushed: 0,
+            len: 0,
+        }
+    }
+
+    pub fn set_all(&mut self) {
+        for i in 0..self.capacity() {
+            self.push(i as u32);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.order_of[id as usize] != Self::NONE
+    }
+
+    pub fn push(&mut self, id: u32) {
+        if self.contains(id) {
+            return;
+        }
+        self.order_of[id as usize] = self.no_pushed;
+        self.queue.push_back((id, self.no_pushed));
+        self.no_pushed += 1;
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        assert!(self.contains(id));
+        self.order_of[id as usize] = Self::NONE;
+        self.len -= 1;
+        if self.queue.front().is_some_and(|&(front, _)| front == id) {
+            self.queue.pop_front();
+        }
+    }
+
+    pub fn front(&mut self) -> u32 {
+        assert!(!self.is_empty());
+        loop {
+            let &(id, order) = self.queue.front().unwrap();
+            if self.order_of[id as usize] == order {
+                return id;
+            }
+            self.queue.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.order_of.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IntQueue;
+
+    #[test]
+    fn test_int_queue() {
+        let mut queue = IntQueue::new(10);
+        for id in [3, 1, 4, 1, 5] {
+            queue.push(id);
+        }
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.front(), 3);
+
+        // 途中の要素を消しても順番は保たれる
+        queue.remove(4);
+        queue.remove(3);
+        assert_eq!(queue.front(), 1);
+        queue.remove(1);
+        assert_eq!(queue.front(), 5);
+
+        // 消した要素を再度追加すると末尾に入る
+        queue.push(3);
+        queue.push(4);
+        queue.remove(5);
+        assert_eq!(queue.front(), 3);
+        queue.remove(3);
+        assert_eq!(queue.front(), 4);
+        queue.remove(4);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_int_queue_remove_and_push() {
+        let mut queue = IntQueue::new(10);
+        for id in [0, 1, 2] {
+            queue.push(id);
+        }
+
+        // 途中で消して再度追加した要素は、古い位置ではなく末尾から取り出す
+        queue.remove(1);
+        queue.push(1);
+        assert_eq!(queue.len(), 3);
+        let mut order = vec![];
+        while !queue.is_empty() {
+            let id = queue.front();
+            order.push(id);
+            queue.remove(id);
+        }
+        assert_eq!(order, vec![0, 2, 1]);
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::solution::Solution;
+
+// segment の並び。各要素は (segment の番号, 反転しているか)
+type SegmentOrder = Vec<(usize, bool)>;
+
+// 任意の k-opt 近傍 (削除する辺と追加する辺の組) を表現する
+// 削除した辺で巡回路を k 個の segment に分割し、追加する辺で segment を繋ぎ直したときの巡回路を管理する
+pub struct KOptMove {
+    // (start, end) の順に並んだ segment。巡回路の向きに沿って start -> end と進む
+    segment_list: Vec<(u32, u32)>,
+    // 繋ぎ直した後にできる巡回路
+    cycle_list: Vec<SegmentOrder>,
+}
+
+impl KOptMove {
+    // removed / added の辺の向きは問わない
+    // 近傍として成立していない (削除辺が巡回路上にない、追加辺が既存の辺と重複する等) 場合は None
+    pub fn new(
+        solution: &impl Solution,
+        removed: &[(u32, u32)],
+        added: &[(u32, u32)],
+    ) -> Option<KOptMove> {
+        if removed.len() < 2 || removed.len() != added.len() {
+            return None;
+        }
+
+        // 削除辺を (id, next(id)) の向きに揃えて、巡回路上の位置でソート
+        let mut cut_list = vec![];
+        for &(id1, id2) in removed.iter() {
+            let cut = if solution.next(id1) == id2 {
+                (id1, id2)
+            } else if solution.next(id2) == id1 {
+                (id2, id1)
+            } else {
+                return None;
+            };
+            if cut_list.contains(&cut) {
+                return None;
+            }
+            cut_list.push(cut);
+        }
+        cut_list.sort_by_key(|&(from, _)| solution.index_of(from));
+
+        for &(id1, id2) in added.iter() {
+            if id1 == id2 || solution.next(id1) == id2 || solution.next(id2) == id1 {
+                return None;
+            }
+        }
+
+        let k = cut_list.len();
+        let segment_list = (0..k)
+            .map(|i| (cut_list[i].1, cut_list[(i + 1) % k].0))
+            .collect::<Vec<_>>();
+
+        // 各端点について、接続する追加辺の番号を列挙しておく
+        let mut endpoint_list: Vec<(u32, usize)> = vec![];
+        for (index, &(id1, id2)) in added.iter().enumerate() {
+            endpoint_list.push((id1, index));
+            endpoint_list.push((id2, index));
+        }
+        // 追加辺の端点は削除辺の端点と一致していなければならない (次数が 2 に保たれる)
+        for &(start, end) in segment_list.iter() {
+            let expected = if start == end { 2 } else { 1 };
+            for id in [start, end] {
+                let count = endpoint_list.iter().filter(|(v, _)| *v == id).count();
+                if count != expected {
+                    return None;
+                }
+            }
+        }
+        if endpoint_list.len() != 2 * k {
+            return None;
+        }
+
+        let mut cycle_list = vec![];
+        let mut visited = vec![false; k];
+        for first in 0..k {
+            if visited[first] {
+                continue;
+            }
+            let mut cycle = vec![];
+            let mut segment = first;
+            let mut reversed = false;
+            let mut entry_edge = usize::MAX;
+            loop {
+                visited[segment] = true;
+                cycle.push((segment, reversed));
+
+                let (start, end) = segment_list[segment];
+                let exit = if reversed { start } else { end };
+                let &(_, edge) = endpoint_list
+                    .iter()
+                    .find(|&&(v, e)| v == exit && e != entry_edge)
+                    .unwrap();
+                let (id1, id2) = added[edge];
+                let next_id = if id1 == exit { id2 } else { id1 };
+
+                let next_segment = segment_list
+                    .iter()
+                    .position(|&(start, end)| start == next_id || end == next_id)
+                    .unwrap();
+                if next_segment == first {
+                    break;
+                }
+                let (next_start, _) = segment_list[next_segment];
+                segment = next_segment;
+                reversed = next_start != next_id;
+                entry_edge = edge;
+            }
+            cycle_list.push(cycle);
+        }
+
+        Some(KOptMove {
+            segment_list,
+            cycle_list,
+        })
+    }
+
+    pub fn no_cycle(&self) -> usize {
+        self.cycle_list.len()
+    }
+
+    // 繋ぎ直した結果が 1 つの巡回路になっているか
+    pub fn is_feasible(&self) -> bool {
+        self.no_cycle() == 1
+    }
+
+    // 繋ぎ直した後に id が属する巡回路の番号
+    // solution は KOptMove::new に渡したものと同じ巡回路でなければならない
+    pub fn cycle_of(&self, solution: &impl Solution, id: u32) -> usize {
+        let segment = self
+            .segment_list
+            .iter()
+            .position(|&(start, end)| solution.between(id, start, end))
+            .unwrap();
+        self.cycle_list
+            .iter()
+            .position(|cycle| cycle.iter().any(|&(s, _)| s == segment))
+            .unwrap()
+    }
+
+    // 繋ぎ直しを Solution::swap の列に変換する
+    // segment の並びと向きを状態として、区間反転の最短手順を幅優先探索で求める
+    pub fn to_swap_list(&self) -> Vec<(u32, u32)> {
+        assert!(self.is_feasible());
+
+        let k = self.segment_list.len();
+        // 1 頂点だけの segment は向きを区別しない
+        let normalize = |state: &mut SegmentOrder| {
+            for (segment, reversed) in state.iter_mut() {
+                let (start, end) = self.segment_list[*segment];
+                if start == end {
+                    *reversed = false;
+                }
+            }
+        };
+        let reverse = |state: &SegmentOrder, from: usize, to: usize| {
+            let mut next_state = state.clone();
+            for i in from..=to {
+                let (segment, reversed) = state[from + to - i];
+                next_state[i] = (segment, !reversed);
+            }
+            next_state
+        };
+
+        let mut target = self.cycle_list[0].clone();
+        normalize(&mut target);
+        let initial = (0..k).map(|i| (i, false)).collect::<Vec<_>>();
+
+        // segment 0 は固定して、残りの区間のみ反転する
+        let mut parent: HashMap<SegmentOrder, (SegmentOrder, usize, usize)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(initial.clone());
+        parent.insert(initial.clone(), (initial.clone(), 0, 0));
+        while let Some(state) = queue.pop_front() {
+            if state == target {
+                break;
+            }
+            for from in 1..k {
+                for to in from..k {
+                    let mut next_state = reverse(&state, from, to);
+                    normalize(&mut next_state);
+                    if !parent.contains_key(&next_state) {
+                        parent.insert(next_state.clone(), (state.clone(), from, to));
+                        queue.push_back(next_state);
+                    }
+                }
+            }
+        }
+
+        let mut range_list = vec![];
+        let mut state = target;
+        while state != initial {
+            let (prev_state, from, to) = parent[&state].clone();
+            range_list.push((from, to));
+            state = prev_state;
+        }
+        range_list.reverse();
+
+        // 区間反転を、その時点での両端の頂点 id に変換
+        let mut state = initial;
+        let mut swap_list = vec![];
+        for (from, to) in range_list.into_iter() {
+            let (from_segment, from_reversed) = state[from];
+            let (to_segment, to_reversed) = state[to];
+            let from_id = if from_reversed {
+                self.segment_list[from_segment].1
+            } else {
+                self.segment_list[from_segment].0
+            };
+            let to_id = if to_reversed {
+                self.segment_list[to_segment].0
+            } else {
+                self.segment_list[to_segment].1
+            };
+            swap_list.push((from_id, to_id));
+            state = reverse(&state, from, to);
+            normalize(&mut state);
+        }
+        swap_list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{seq::SliceRandom, thread_rng, Rng};
+
+    use crate::{array_solution::ArraySolution, solution::Solution};
+
+    use super::KOptMove;
+
+    // 辺集合から連結成分の数を数える
+    fn count_cycle(n: usize, edge_list: &[(u32, u32)]) -> usize {
+        let mut adjacent = vec![vec![]; n];
+        for &(id1, id2) in edge_list.iter() {
+            adjacent[id1 as usize].push(id2);
+            adjacent[id2 as usize].push(id1);
+        }
+        let mut visited = vec![false; n];
+        let mut count = 0;
+        for start in 0..n {
+            if visited[start] {
+                continue;
+            }
+            count += 1;
+            let mut stack = vec![start];
+            visited[start] = true;
+            while let Some(id) = stack.pop() {
+                for &next in adjacent[id].iter() {
+                    if !visited[next as usize] {
+                        visited[next as usize] = true;
+                        stack.push(next as usize);
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_kopt_move_random() {
+        const SIZE: usize = 30;
+        let mut rng = thread_rng();
+
+        let mut no_feasible = 0;
+        for _iter in 0..2000 {
+            let mut content = (0..SIZE as u32).collect::<Vec<_>>();
+            content.shuffle(&mut rng);
+            let mut solution = ArraySolution::from_array(content);
+
+            let k = rng.gen_range(2..=5);
+            let mut from_list = (0..SIZE as u32).collect::<Vec<_>>();
+            from_list.shuffle(&mut rng);
+            let removed = from_list
+                .iter()
+                .take(k)
+                .map(|&id| (id, solution.next(id)))
+                .collect::<Vec<_>>();
+
+            let mut endpoint_list = removed
+                .iter()
+                .flat_map(|&(id1, id2)| [id1, id2])
+                .collect::<Vec<_>>();
+            endpoint_list.shuffle(&mut rng);
+            let added = endpoint_list
+                .chunks(2)
+                .map(|pair| (pair[0], pair[1]))
+                .collect::<Vec<_>>();
+
+            let mut edge_list = vec![];
+            for id in 0..SIZE as u32 {
+                let edge = (id, solution.next(id));
+                if !removed.contains(&edge) {
+                    edge_list.push(edge);
+                }
+            }
+            edge_list.extend(added.iter().cloned());
+
+            let kopt_move = match KOptMove::new(&solution, &removed, &added) {
+                Some(kopt_move) => kopt_move,
+                None => continue,
+            };
+            assert_eq!(kopt_move.no_cycle(), count_cycle(SIZE, &edge_list));
+
+            if kopt_move.is_feasible() {
+                no_feasible += 1;
+                for (from, to) in kopt_move.to_swap_list() {
+                    solution.swap(from, to);
+                }
+                for &(id1, id2) in edge_list.iter() {
+                    assert!(solution.next(id1) == id2 || solution.prev(id1) == id2);
+                }
+            }
+        }
+        assert!(no_feasible > 0);
+    }
+
+    #[test]
+    fn test_double_bridge() {
+        const SIZE: usize = 20;
+        let mut solution = ArraySolution::new(SIZE);
+
+        // [0..4] [5..9] [10..14] [15..19] -> [0..4] [15..19] [10..14] [5..9]
+        let removed = [(4, 5), (9, 10), (14, 15), (19, 0)];
+        let added = [(4, 15), (19, 10), (14, 5), (9, 0)];
+
+        // 前半の 2 辺だけでは巡回路が 2 つに分かれる
+        let half = KOptMove::new(&solution, &removed[..2], &[(4, 10), (9, 5)]).unwrap();
+        assert_eq!(half.no_cycle(), 2);
+        // [5..9] と [10..19, 0..4] に分かれる
+        assert_eq!(half.cycle_of(&solution, 5), half.cycle_of(&solution, 9));
+        assert_eq!(half.cycle_of(&solution, 10), half.cycle_of(&solution, 4));
+        assert_ne!(half.cycle_of(&solution, 7), half.cycle_of(&solution, 0));
+
+        let kopt_move = KOptMove::new(&solution, &removed, &added).unwrap();
+        assert!(kopt_move.is_feasible());
+        for (from, to) in kopt_move.to_swap_list() {
+            solution.swap(from, to);
+        }
+        let expected = [
+            0, 1, 2, 3, 4, 15, 16, 17, 18, 19, 10, 11, 12, 13, 14, 5, 6, 7, 8, 9,
+        ];
+        let mut id = 0;
+        for expected_id in expected {
+            assert_eq!(id, expected_id);
+            id = solution.next(id);
+        }
+    }
+}
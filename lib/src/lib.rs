@@ -1,14 +1,36 @@
+pub mod activation_order;
 pub mod array_solution;
 mod bitset;
+pub mod cvrp;
 pub mod distance;
 pub mod divide_and_conqure_solver;
+pub mod eax;
+pub mod edge_constraint;
 pub mod euclid_distance;
 pub mod evaluate;
+pub mod exact;
+pub mod gpx;
+pub mod gtsp;
+pub mod held_karp;
+pub mod improvement_strategy;
 mod intset;
+mod kopt_move;
 pub mod lkh;
+pub mod matrix_distance;
+pub mod mtsp;
+pub mod multi_trial_lkh;
 mod neighbor_table;
+pub mod open_path;
 pub mod opt2;
 pub mod opt3;
+pub mod parallel_lkh;
+pub mod position_objective;
 mod segment_tree;
 pub mod solution;
+pub mod stop_reason;
+pub mod symmetric_transform;
+#[cfg(test)]
+mod test_util;
+pub mod tsptw;
 pub mod two_level_tree_solution;
+mod union_find;
@@ -3,10 +3,21 @@ use std::{path::PathBuf, time::Instant};
 use rand::{rngs::ThreadRng, Rng};
 
 use crate::{
-    array_solution::ArraySolution, bitset::BitSet, distance::DistanceFunction, evaluate::evaluate,
-    intset::IntSet, neighbor_table::NeighborTable, segment_tree::SegmentTree, solution::Solution,
+    activation_order::{ActivationOrder, ActiveSet},
+    array_solution::ArraySolution,
+    bitset::BitSet,
+    distance::DistanceFunction,
+    edge_constraint::is_allowed_change,
+    evaluate::evaluate,
+    improvement_strategy::{ImprovementSelector, ImprovementStrategy},
+    kopt_move::KOptMove,
+    neighbor_table::NeighborTable,
+    segment_tree::SegmentTree,
+    solution::Solution,
+    stop_reason::TIME_CHECK_INTERVAL,
 };
 
+#[allow(clippy::too_many_arguments)]
 fn solve_inner<'a, T: Solution>(
     depth: usize,
     max_depth: usize,
@@ -16,19 +27,21 @@ fn solve_inner<'a, T: Solution>(
     best_flip: &mut SegmentTree<'a, T>,
     edge_stack: &mut Vec<(u32, u32)>,
     gain: i64,
-    best_gain: &mut i64,
+    selector: &mut ImprovementSelector<()>,
     selected: &mut BitSet,
     rng: &mut ThreadRng,
 ) {
     if depth == max_depth {
         // 評価して最も良いゲインのものを保存
-        if *best_gain < gain {
-            *best_gain = gain;
-            best_flip.copy_from(&current_flip);
+        let best_gain = selector.best_gain();
+        selector.update(gain, ());
+        if selector.best_gain() > best_gain {
+            best_flip.copy_from(current_flip);
         }
         return;
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn check<'a, T: Solution>(
         depth: usize,
         max_depth: usize,
@@ -38,7 +51,7 @@ fn solve_inner<'a, T: Solution>(
         best_flip: &mut SegmentTree<'a, T>,
         edge_stack: &mut Vec<(u32, u32)>,
         gain: i64,
-        best_gain: &mut i64,
+        selector: &mut ImprovementSelector<()>,
         selected: &mut BitSet,
         f1: u32,
         t1: u32,
@@ -49,6 +62,9 @@ fn solve_inner<'a, T: Solution>(
         if selected.test(f2) || selected.test(t2) {
             return;
         }
+        if !is_allowed_change(distance, &[(f1, t1), (f2, t2)], &[(f1, f2), (t1, t2)]) {
+            return;
+        }
         selected.set(f2);
         selected.set(t2);
         current_flip.swap(t1, f2);
@@ -70,11 +86,14 @@ fn solve_inner<'a, T: Solution>(
                 best_flip,
                 edge_stack,
                 gain + partial_gain,
-                best_gain,
+                selector,
                 selected,
                 rng,
             );
             edge_stack.pop();
+            if selector.is_satisfied() {
+                break;
+            }
         }
 
         current_flip.undo();
@@ -88,6 +107,9 @@ fn solve_inner<'a, T: Solution>(
 
     if rng.gen_bool(0.5) {
         for f2 in neighbor_table.neighbor_list(f1) {
+            if selector.is_satisfied() {
+                return;
+            }
             let t2 = current_flip.next(*f2);
             check(
                 depth,
@@ -98,7 +120,7 @@ fn solve_inner<'a, T: Solution>(
                 best_flip,
                 edge_stack,
                 gain,
-                best_gain,
+                selector,
                 selected,
                 f1,
                 t1,
@@ -109,6 +131,9 @@ fn solve_inner<'a, T: Solution>(
         }
     } else {
         for t2 in neighbor_table.neighbor_list(t1) {
+            if selector.is_satisfied() {
+                return;
+            }
             let f2 = current_flip.prev(*t2);
             check(
                 depth,
@@ -119,7 +144,7 @@ fn solve_inner<'a, T: Solution>(
                 best_flip,
                 edge_stack,
                 gain,
-                best_gain,
+                selector,
                 selected,
                 f1,
                 t1,
@@ -131,6 +156,263 @@ fn solve_inner<'a, T: Solution>(
     }
 }
 
+// 逐次的な k-opt では見つからない double-bridge 型の改善を探す (LKH の Gain23 相当)
+// 単体では巡回路が 2 つに分かれてしまう逐次的な 2-opt / 3-opt を前半として、
+// 分かれた 2 つの巡回路を繋ぎ直す 2-opt を後半として組み合わせ、非逐次的な 4-opt / 5-opt を作る
+fn search_non_sequential(
+    distance: &impl DistanceFunction,
+    neighbor_table: &NeighborTable,
+    solution: &ArraySolution,
+    t1: u32,
+) -> Option<(i64, Vec<(u32, u32)>)> {
+    let dist = |i1, i2| distance.distance(i1, i2);
+
+    // (削除する辺, 追加する辺, 辺の数, 前半のゲイン) の列挙
+    // 前半を閉じた時点でゲインが正のものだけを残す。合計が正の組なら、どちらかの半分は正なので
+    // そちらを前半とする探索で見つかる
+    // 探索中に Vec を作らないよう、辺は固定長の配列に詰めて先頭 k 本を使う
+    let mut half_list = vec![];
+    for t2 in [solution.prev(t1), solution.next(t1)] {
+        for &t3 in neighbor_table.neighbor_list(t2) {
+            let g1 = dist(t1, t2) - dist(t2, t3);
+            if g1 <= 0 || t3 == t1 {
+                continue;
+            }
+            for t4 in [solution.prev(t3), solution.next(t3)] {
+                if t4 == t1 || t4 == t2 {
+                    continue;
+                }
+                let g2 = g1 + dist(t3, t4);
+
+                let half_gain = g2 - dist(t4, t1);
+                if half_gain > 0 {
+                    half_list.push((
+                        [(t1, t2), (t3, t4), (0, 0)],
+                        [(t2, t3), (t4, t1), (0, 0)],
+                        2,
+                        half_gain,
+                    ));
+                }
+
+                for &t5 in neighbor_table.neighbor_list(t4) {
+                    let g3 = g2 - dist(t4, t5);
+                    if g3 <= 0 || t5 == t1 || t5 == t3 {
+                        continue;
+                    }
+                    for t6 in [solution.prev(t5), solution.next(t5)] {
+                        if [t1, t2, t3, t4].contains(&t6) {
+                            continue;
+                        }
+                        let half_gain = g3 + dist(t5, t6) - dist(t6, t1);
+                        if half_gain > 0 {
+                            half_list.push((
+                                [(t1, t2), (t3, t4), (t5, t6)],
+                                [(t2, t3), (t4, t5), (t6, t1)],
+                                3,
+                                half_gain,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut best_gain = 0;
+    let mut best_move = None;
+
+    // 後半の候補ごとに使い回すバッファ
+    let mut start_list = vec![];
+    let mut all_removed = Vec::with_capacity(5);
+    let mut all_added = Vec::with_capacity(5);
+
+    for (removed, added, k, half_gain) in half_list.into_iter() {
+        let (removed, added) = (&removed[..k], &added[..k]);
+        // 前半で巡回路が 2 つに分かれる場合だけ、その 2 つを繋ぎ直す後半を探す
+        let Some(half_move) =
+            KOptMove::new(solution, removed, added).filter(|kopt_move| kopt_move.no_cycle() == 2)
+        else {
+            continue;
+        };
+        let is_removed = |id1: u32, id2: u32| {
+            removed
+                .iter()
+                .any(|&edge| edge == (id1, id2) || edge == (id2, id1))
+        };
+
+        // 後半は前半の端点とその近傍から始める
+        start_list.clear();
+        for &(id1, id2) in removed.iter() {
+            for id in [id1, id2] {
+                start_list.push(id);
+                start_list.extend(neighbor_table.neighbor_list(id).iter());
+            }
+        }
+        start_list.sort_unstable();
+        start_list.dedup();
+
+        // (s1, s2) と (s3, s4) を別々の巡回路から 1 本ずつ削除すれば、繋ぎ直した結果は 1 つの巡回路になる
+        for &s1 in start_list.iter() {
+            let cycle1 = half_move.cycle_of(solution, s1);
+            for s2 in [solution.prev(s1), solution.next(s1)] {
+                if is_removed(s1, s2) {
+                    continue;
+                }
+                let g1 = half_gain + dist(s1, s2);
+                for &s3 in neighbor_table.neighbor_list(s2) {
+                    let g2 = g1 - dist(s2, s3);
+                    if g2 <= 0 || half_move.cycle_of(solution, s3) == cycle1 {
+                        continue;
+                    }
+                    for s4 in [solution.prev(s3), solution.next(s3)] {
+                        if is_removed(s3, s4) {
+                            continue;
+                        }
+                        let gain = g2 + dist(s3, s4) - dist(s4, s1);
+                        if gain <= best_gain {
+                            continue;
+                        }
+
+                        all_removed.clear();
+                        all_removed.extend_from_slice(removed);
+                        all_removed.extend([(s1, s2), (s3, s4)]);
+                        all_added.clear();
+                        all_added.extend_from_slice(added);
+                        all_added.extend([(s2, s3), (s4, s1)]);
+                        if !is_allowed_change(distance, &all_removed, &all_added) {
+                            continue;
+                        }
+
+                        if let Some(kopt_move) = KOptMove::new(solution, &all_removed, &all_added) {
+                            if kopt_move.is_feasible() {
+                                best_gain = gain;
+                                best_move = Some(kopt_move);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    best_move.map(|kopt_move| (best_gain, kopt_move.to_swap_list()))
+}
+
+// kick の起点にする辺 (a, next(a)) をランダムに選ぶ
+// 固定辺は swap で消せないので避ける。選択済みの頂点を含まない辺が見つからなければ None
+fn select_kick_edge(
+    distance: &impl DistanceFunction,
+    solution: &ArraySolution,
+    selected: &BitSet,
+    rng: &mut impl Rng,
+) -> Option<(u32, u32)> {
+    let n = solution.len();
+    (0..n).find_map(|_| {
+        let a = rng.gen_range(0..n as u32);
+        let b = solution.next(a);
+        (!selected.test(a) && !selected.test(b) && !distance.is_fixed(a, b)).then_some((a, b))
+    })
+}
+
+// random 2-opt kick
+// 近い部分のエッジを強制的に結ぶ kick
+// どうせ kick するなら、ある点の近傍をたくさん kick した方が変化させる意味があるから、
+// chain させる感じで変化をさせる。
+// 評価値の変化量を返す。1 歩も打てなかったときも dlb が空のままにならないよう、起点を入れておく
+fn kick(
+    distance: &impl DistanceFunction,
+    neighbor_table: &NeighborTable,
+    solution: &mut ArraySolution,
+    dlb: &mut ActiveSet,
+    no_random_step: usize,
+    rng: &mut impl Rng,
+) -> i64 {
+    let n = solution.len();
+    let mut selected = BitSet::new(n);
+    let Some((mut a, mut b)) = select_kick_edge(distance, solution, &selected, rng) else {
+        // 固定辺しかなく、kick できない
+        dlb.push(rng.gen_range(0..n as u32));
+        return 0;
+    };
+    selected.set(a);
+    selected.set(b);
+
+    let mut diff = 0;
+    'kick: for _step in 0..no_random_step {
+        // swap で張り替わるのは [(prev(b), b), (c, d)] -> [(prev(b), c), (b, d)] なので、
+        // 選択済みの頂点を含まず、固定辺を消さず禁止辺も張らない c を候補にする
+        let mut no_retry = 0;
+        let (b_prev, candidate_list) = loop {
+            let b_prev = solution.prev(b);
+            if !distance.is_fixed(b_prev, b) {
+                let candidate_list = neighbor_table
+                    .neighbor_list(a)
+                    .iter()
+                    .cloned()
+                    .filter(|&c| {
+                        let d = solution.next(c);
+                        !selected.test(c)
+                            && !selected.test(d)
+                            && is_allowed_change(
+                                distance,
+                                &[(b_prev, b), (c, d)],
+                                &[(b_prev, c), (b, d)],
+                            )
+                    })
+                    .collect::<Vec<_>>();
+                if !candidate_list.is_empty() {
+                    break (b_prev, candidate_list);
+                }
+            }
+            // 近傍が選択済みの頂点で埋まった領域から抜け出せないことがあるので、
+            // 一定回数ごとにランダムな頂点から選び直し、それでも見つからなければ kick を打ち切る
+            no_retry += 1;
+            if no_retry > n {
+                break 'kick;
+            }
+            if no_retry % KICK_RETRY_INTERVAL == 0 || distance.is_fixed(b_prev, b) {
+                // (prev(b), b) が固定辺なら a をいくら選び直しても候補がないので、起点の辺ごと選び直す
+                let Some(edge) = select_kick_edge(distance, solution, &selected, rng) else {
+                    break 'kick;
+                };
+                (a, b) = edge;
+                selected.set(a);
+                selected.set(b);
+            } else {
+                let a_size = neighbor_table.neighbor_list(a).len();
+                let a_idx = rng.gen_range(0..a_size);
+                a = neighbor_table.neighbor_list(a)[a_idx];
+            }
+        };
+
+        let c = candidate_list[rng.gen_range(0..candidate_list.len())];
+        let d = solution.next(c);
+        selected.set(c);
+        selected.set(d);
+
+        // a は近傍を辿って選び直されることがあり、b の直前とは限らないので、
+        // swap で実際に張り替わる辺の差分を取る
+        diff += distance.distance(b_prev, c) + distance.distance(b, d)
+            - distance.distance(b_prev, b)
+            - distance.distance(c, d);
+        solution.swap(b, c);
+        for id in [a, b, c, d] {
+            dlb.push(id);
+        }
+
+        (a, b) = (b, d);
+    }
+    if dlb.is_empty() {
+        dlb.push(a);
+        dlb.push(b);
+    }
+    diff
+}
+
+// kick で近傍を辿って頂点を選び直すとき、ランダムな頂点へ飛ぶ間隔
+const KICK_RETRY_INTERVAL: usize = 10;
+
+#[derive(Clone)]
 pub struct LKHConfig {
     pub use_neighbor_cache: bool,
     pub cache_filepath: PathBuf,
@@ -141,52 +423,143 @@ pub struct LKHConfig {
     pub end_kick_step: usize,
     pub fail_count_threashold: u32,
     pub max_depth: usize,
+    pub use_non_sequential_move: bool,
+    pub improvement_strategy: ImprovementStrategy,
+    pub activation_order: ActivationOrder,
 }
 
 pub fn solve(
     distance: &(impl DistanceFunction + std::marker::Sync),
-    mut solution: ArraySolution,
+    solution: ArraySolution,
     config: LKHConfig,
 ) -> ArraySolution {
-    let n = distance.dimension() as usize;
-    // 解く
+    let neighbor_table = NeighborTable::load_or_build(
+        distance,
+        5,
+        config.use_neighbor_cache,
+        &config.cache_filepath,
+    );
+    solve_with_neighbor_table(distance, &neighbor_table, solution, config)
+}
 
-    let start = Instant::now();
+// 候補リストを差し替えて探索したい呼び出し元 (multi trial など) 向け
+pub(crate) fn solve_with_neighbor_table(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    neighbor_table: &NeighborTable,
+    solution: ArraySolution,
+    config: LKHConfig,
+) -> ArraySolution {
+    let mut state = LKHState::new(distance, solution, &config);
+    run(distance, neighbor_table, &mut state, &config);
+    state.best_solution
+}
 
-    let neighbor_table = if config.use_neighbor_cache && config.cache_filepath.exists() {
-        NeighborTable::load(&config.cache_filepath)
-    } else {
-        let table = NeighborTable::new(distance, 5);
-        if config.use_neighbor_cache {
-            table.save(&config.cache_filepath);
+// 探索の途中状態
+// 制限時間を区切って何度も run を呼ぶ呼び出し元 (parallel など) が、kick の段階や dlb を引き継げるようにする
+pub(crate) struct LKHState {
+    solution: ArraySolution,
+    eval: i64,
+    dlb: ActiveSet,
+    best_solution: ArraySolution,
+    best_eval: i64,
+    no_random_step: usize,
+    no_continuous_fail_count: u32,
+}
+
+impl LKHState {
+    pub(crate) fn new(
+        distance: &impl DistanceFunction,
+        solution: ArraySolution,
+        config: &LKHConfig,
+    ) -> LKHState {
+        assert!(
+            distance.is_symmetric(),
+            "LKH requires a symmetric distance; use SymmetricTransform"
+        );
+        let n = distance.dimension() as usize;
+        let mut dlb = ActiveSet::new(n, config.activation_order);
+        dlb.set_all();
+        let eval = evaluate(distance, &solution);
+        LKHState {
+            best_solution: solution.clone(),
+            best_eval: eval,
+            solution,
+            eval,
+            dlb,
+            no_random_step: config.start_kick_step,
+            no_continuous_fail_count: 0,
         }
-        table
-    };
+    }
 
-    let mut rng = rand::thread_rng();
+    pub(crate) fn best_eval(&self) -> i64 {
+        self.best_eval
+    }
 
-    let mut dlb = IntSet::new(n);
-    dlb.set_all();
+    pub(crate) fn best_solution(&self) -> &ArraySolution {
+        &self.best_solution
+    }
 
-    let mut eval = evaluate(distance, &solution);
-    let mut selected = BitSet::new(n);
+    // 他の探索で見つかった解に乗り換える
+    // kick の段階は引き継ぎ、今の解から張り替わった辺の端点だけを探索し直す
+    pub(crate) fn adopt(&mut self, solution: &ArraySolution, eval: i64) {
+        for id in 0..solution.len() as u32 {
+            let next = solution.next(id);
+            if self.solution.next(id) != next && self.solution.prev(id) != next {
+                self.dlb.push(id);
+                self.dlb.push(next);
+            }
+        }
+        self.solution.copy_from(solution);
+        self.eval = eval;
+        self.best_solution.copy_from(solution);
+        self.best_eval = eval;
+    }
+}
+
+// state から config.time_ms だけ探索を進める
+pub(crate) fn run(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    neighbor_table: &NeighborTable,
+    state: &mut LKHState,
+    config: &LKHConfig,
+) {
+    let n = distance.dimension() as usize;
+    // 解く
 
-    let mut global_best_eval = eval;
-    let mut global_best_solution = solution.clone();
+    let start = Instant::now();
 
-    let mut no_random_step = config.start_kick_step;
-    let mut no_continuous_fail_count = 0;
+    let mut rng = rand::thread_rng();
+
+    let LKHState {
+        solution,
+        eval,
+        dlb,
+        best_solution: global_best_solution,
+        best_eval: global_best_eval,
+        no_random_step,
+        no_continuous_fail_count,
+    } = state;
+    let mut selected = BitSet::new(n);
 
     for iter in 0.. {
-        let a = dlb.random_select(&mut rng);
+        // 局所探索が収束する前でも、一定間隔で制限時間を確認して打ち切る
+        if iter % TIME_CHECK_INTERVAL == 0 && start.elapsed().as_millis() > config.time_ms {
+            if *global_best_eval > *eval {
+                *global_best_eval = *eval;
+                global_best_solution.copy_from(solution);
+            }
+            break;
+        }
+
+        let a = dlb.select(&mut rng);
 
         selected.clear_all();
 
         let diff = {
-            let mut current_tree = SegmentTree::new(&solution);
-            let mut best_tree = SegmentTree::new(&solution);
+            let mut current_tree = SegmentTree::new(solution);
+            let mut best_tree = SegmentTree::new(solution);
 
-            let mut best_gain = 0;
+            let mut selector = ImprovementSelector::new(config.improvement_strategy);
 
             let a_next = solution.next(a);
             let a_prev = solution.prev(a);
@@ -204,12 +577,12 @@ pub fn solve(
                         1,
                         max_depth,
                         distance,
-                        &neighbor_table,
+                        neighbor_table,
                         &mut current_tree,
                         &mut best_tree,
                         &mut edge_stack,
                         0,
-                        &mut best_gain,
+                        &mut selector,
                         &mut selected,
                         &mut rng,
                     );
@@ -217,21 +590,27 @@ pub fn solve(
                     selected.clear(a);
                     selected.clear(b);
                     edge_stack.pop();
+
+                    if selector.is_satisfied() {
+                        break;
+                    }
                 }
 
-                if best_gain > 0 {
+                if selector.best_gain() > 0 {
                     break;
                 }
             }
-            if best_gain > 0 {
-                Some((best_gain, best_tree.to_swap_list()))
+            if selector.best_gain() > 0 {
+                Some((selector.best_gain(), best_tree.into_swap_list()))
+            } else if config.use_non_sequential_move {
+                search_non_sequential(distance, neighbor_table, solution, a)
             } else {
                 None
             }
         };
 
         if let Some((gain, edge_list)) = diff {
-            eval -= gain;
+            *eval -= gain;
             for (from, to) in edge_list.into_iter() {
                 solution.swap(from, to);
                 dlb.push(from);
@@ -253,69 +632,184 @@ pub fn solve(
                 eprintln!("dlb size: {}", dlb.len());
             }
 
-            if global_best_eval > eval {
-                global_best_eval = eval;
-                global_best_solution.copy_from(&solution);
-                no_continuous_fail_count = 0;
+            if *global_best_eval > *eval {
+                *global_best_eval = *eval;
+                global_best_solution.copy_from(solution);
+                *no_continuous_fail_count = 0;
             } else {
-                solution.copy_from(&global_best_solution);
-                no_continuous_fail_count += 1;
+                solution.copy_from(global_best_solution);
+                *eval = *global_best_eval;
+                *no_continuous_fail_count += 1;
             }
 
-            if no_continuous_fail_count == config.fail_count_threashold {
-                no_random_step = (config.end_kick_step).min(no_random_step + config.kick_step_diff);
-                no_continuous_fail_count = 0;
+            if *no_continuous_fail_count == config.fail_count_threashold {
+                *no_random_step =
+                    (config.end_kick_step).min(*no_random_step + config.kick_step_diff);
+                *no_continuous_fail_count = 0;
             }
 
-            // random 2-opt kick
-            // 近い部分のエッジを強制的に結ぶ kick
-            // どうせ kick するなら、ある点の近傍をたくさん kick した方が変化させる意味があるから、
-            // chain させる感じで変化をさせる。
-            let mut a = rng.gen_range(0..n as u32);
-            let mut b = solution.next(a);
-
-            let mut selected = BitSet::new(n);
-            selected.set(a);
-            selected.set(b);
-
-            for _step in 0..no_random_step {
-                while neighbor_table
-                    .neighbor_list(a)
-                    .iter()
-                    .all(|v| selected.test(*v) || selected.test(solution.next(*v)))
-                {
-                    let a_size = neighbor_table.neighbor_list(a).len();
-                    let a_idx = rng.gen_range(0..a_size);
-                    a = neighbor_table.neighbor_list(a)[a_idx];
-                }
+            *eval += kick(
+                distance,
+                neighbor_table,
+                solution,
+                dlb,
+                *no_random_step,
+                &mut rng,
+            );
+            debug_assert_eq!(*eval, evaluate(distance, solution));
 
-                let c_size = neighbor_table.neighbor_list(a).len();
-                let c_idx = rng.gen_range(0..c_size);
-                let mut c = neighbor_table.neighbor_list(a)[c_idx];
-                let mut d = solution.next(c);
+            let end = Instant::now();
+            if (end - start).as_millis() > config.time_ms {
+                break;
+            }
+        }
+    }
+}
 
-                while selected.test(c) || selected.test(d) {
-                    let c_idx = rng.gen_range(0..c_size);
-                    c = neighbor_table.neighbor_list(a)[c_idx];
-                    d = solution.next(c);
-                }
-                selected.set(c);
-                selected.set(d);
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use crate::{
+        activation_order::{ActivationOrder, ActiveSet},
+        array_solution::ArraySolution,
+        edge_constraint::ConstrainedDistance,
+        evaluate::{evaluate, validate},
+        improvement_strategy::ImprovementStrategy,
+        neighbor_table::NeighborTable,
+        solution::Solution,
+        test_util::random_euclid_distance_with,
+    };
 
-                solution.swap(b, c);
-                for id in [a, b, c, d] {
-                    dlb.push(id);
-                }
+    use super::{kick, search_non_sequential, LKHConfig, LKHState};
+
+    #[test]
+    fn test_lkh_state_adopt() {
+        const SIZE: usize = 50;
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let distance = random_euclid_distance_with(SIZE, &mut rng);
+        let config = LKHConfig {
+            use_neighbor_cache: false,
+            cache_filepath: PathBuf::new(),
+            debug: false,
+            time_ms: 0,
+            start_kick_step: 5,
+            kick_step_diff: 5,
+            end_kick_step: 20,
+            fail_count_threashold: 10,
+            max_depth: 5,
+            use_non_sequential_move: false,
+            improvement_strategy: ImprovementStrategy::BestImprovement,
+            activation_order: ActivationOrder::Random,
+        };
+        let mut state = LKHState::new(&distance, ArraySolution::new(SIZE), &config);
+        state.no_random_step = 15;
+        state.dlb = ActiveSet::new(SIZE, ActivationOrder::Random);
+
+        // 2-opt で 2 本の辺だけが張り替わった解に乗り換える
+        let mut solution = ArraySolution::new(SIZE);
+        solution.swap(10, 20);
+        let eval = evaluate(&distance, &solution);
+        state.adopt(&solution, eval);
+
+        assert_eq!(state.best_eval(), eval);
+        assert_eq!(state.eval, eval);
+        // kick の段階は引き継ぎ、張り替わった辺の端点だけを探索し直す
+        assert_eq!(state.no_random_step, 15);
+        assert_eq!(state.dlb.len(), 4);
+    }
 
-                (a, b) = (b, d);
+    #[test]
+    fn test_search_non_sequential() {
+        const SIZE: usize = 100;
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let distance = random_euclid_distance_with(SIZE, &mut rng);
+        let neighbor_table = NeighborTable::new(&distance, 10);
+
+        let mut order = (0..SIZE as u32).collect::<Vec<_>>();
+        order.shuffle(&mut rng);
+        let solution = ArraySolution::from_array(order);
+        let eval = evaluate(&distance, &solution);
+
+        // ランダムな巡回路なら改善する近傍は見つかり、ゲインは実際の評価値の差と一致する
+        let mut no_found = 0;
+        for t1 in 0..10 {
+            let Some((gain, swap_list)) =
+                search_non_sequential(&distance, &neighbor_table, &solution, t1)
+            else {
+                continue;
+            };
+            no_found += 1;
+            assert!(gain > 0);
+
+            let mut moved = solution.clone();
+            for (from, to) in swap_list {
+                moved.swap(from, to);
             }
-            eval = evaluate(distance, &solution);
+            assert!(validate(&distance, &moved));
+            assert_eq!(eval - gain, evaluate(&distance, &moved));
+        }
+        assert!(no_found > 0);
+    }
 
-            let end = Instant::now();
-            if (end - start).as_millis() > config.time_ms {
-                break;
+    #[test]
+    fn test_kick_with_fixed_edges() {
+        const SIZE: u32 = 50;
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let distance = random_euclid_distance_with(SIZE as usize, &mut rng);
+
+        // 初期解 0, 1, ..., n - 1 の辺を 2 本を残して固定する
+        // ランダムな a に対して (a, next(a)) はほぼ固定辺になる
+        let fixed_edge_list = (0..SIZE - 1)
+            .filter(|&i| i != SIZE / 2)
+            .map(|i| (i, i + 1))
+            .collect::<Vec<_>>();
+        let constrained = ConstrainedDistance::new(&distance, &fixed_edge_list, &[]);
+        let neighbor_table = NeighborTable::new(&constrained, 10);
+
+        let mut no_moved = 0;
+        for _iter in 0..100 {
+            let mut solution = ArraySolution::new(SIZE as usize);
+            let mut dlb = ActiveSet::new(SIZE as usize, ActivationOrder::Random);
+            let eval = evaluate(&constrained, &solution);
+            let diff = kick(
+                &constrained,
+                &neighbor_table,
+                &mut solution,
+                &mut dlb,
+                5,
+                &mut rng,
+            );
+            assert!(!dlb.is_empty());
+            assert!(validate(&constrained, &solution));
+            assert_eq!(eval + diff, evaluate(&constrained, &solution));
+            if diff != 0 {
+                no_moved += 1;
             }
         }
+        // 固定されていない辺を起点に選び直すので、kick は打てる
+        assert!(no_moved > 0);
+
+        // 全ての辺が固定されていて kick できなくても、dlb は空にならない
+        let fixed_edge_list = (0..SIZE).map(|i| (i, (i + 1) % SIZE)).collect::<Vec<_>>();
+        let constrained = ConstrainedDistance::new(&distance, &fixed_edge_list, &[]);
+        let mut solution = ArraySolution::new(SIZE as usize);
+        let mut dlb = ActiveSet::new(SIZE as usize, ActivationOrder::Random);
+        let diff = kick(
+            &constrained,
+            &neighbor_table,
+            &mut solution,
+            &mut dlb,
+            5,
+            &mut rng,
+        );
+        assert_eq!(diff, 0);
+        assert!(!dlb.is_empty());
+        assert!(validate(&constrained, &solution));
     }
-    global_best_solution
 }
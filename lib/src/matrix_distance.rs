@@ -0,0 +1,119 @@
+use crate::distance::DistanceFunction;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+// 距離行列で与えられる問題。非対称でもよい
+pub struct MatrixDistance {
+    matrix: Vec<i64>,
+    dimension: u32,
+    symmetric: bool,
+    name: String,
+}
+
+impl MatrixDistance {
+    // matrix[i][j] が i から j への距離
+    pub fn new(matrix: Vec<Vec<i64>>, name: String) -> MatrixDistance {
+        let dimension = matrix.len() as u32;
+        assert!(matrix.iter().all(|row| row.len() == dimension as usize));
+        MatrixDistance::from_flatten(matrix.concat(), dimension, name)
+    }
+
+    fn from_flatten(matrix: Vec<i64>, dimension: u32, name: String) -> MatrixDistance {
+        let n = dimension as usize;
+        let symmetric = (0..n).all(|i| (i + 1..n).all(|j| matrix[i * n + j] == matrix[j * n + i]));
+        MatrixDistance {
+            matrix,
+            dimension,
+            symmetric,
+            name,
+        }
+    }
+
+    // EDGE_WEIGHT_TYPE: EXPLICIT, EDGE_WEIGHT_FORMAT: FULL_MATRIX の TSPLIB 形式 (ATSP を含む) を読む
+    pub fn load_tsplib(filepath: &PathBuf) -> MatrixDistance {
+        let f = File::open(filepath).unwrap();
+        let reader = BufReader::new(f);
+        let name = filepath.file_name().unwrap().to_str().unwrap().to_string();
+
+        let mut dimension = u32::MAX;
+        let mut in_weight_section = false;
+        let mut matrix = vec![];
+
+        for line in reader.lines() {
+            let line = line.unwrap();
+            if line.contains("EOF") {
+                break;
+            }
+
+            if in_weight_section {
+                matrix.extend(
+                    line.split_whitespace()
+                        .map(|token| token.parse::<i64>().unwrap()),
+                );
+            } else if line.contains("DIMENSION") {
+                let dim_token = line.split(':').collect::<Vec<_>>()[1].trim();
+                dimension = dim_token.parse::<u32>().unwrap();
+            } else if line.contains("EDGE_WEIGHT_FORMAT") {
+                assert!(
+                    line.contains("FULL_MATRIX"),
+                    "unsupported edge weight format: {}",
+                    line
+                );
+            } else if line.contains("EDGE_WEIGHT_SECTION") {
+                in_weight_section = true;
+            }
+        }
+        assert_eq!(dimension as usize * dimension as usize, matrix.len());
+        MatrixDistance::from_flatten(matrix, dimension, name)
+    }
+}
+
+impl DistanceFunction for MatrixDistance {
+    fn distance(&self, id1: u32, id2: u32) -> i64 {
+        self.matrix[id1 as usize * self.dimension as usize + id2 as usize]
+    }
+
+    fn dimension(&self) -> u32 {
+        self.dimension
+    }
+
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn is_symmetric(&self) -> bool {
+        self.symmetric
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{distance::DistanceFunction, test_util::temp_filepath};
+
+    use super::MatrixDistance;
+
+    #[test]
+    fn test_load_tsplib() {
+        let filepath = temp_filepath("matrix_distance_test.atsp");
+        let mut f = std::fs::File::create(&filepath).unwrap();
+        writeln!(
+            f,
+            "NAME: test\nTYPE: ATSP\nDIMENSION: 3\nEDGE_WEIGHT_TYPE: EXPLICIT\n\
+             EDGE_WEIGHT_FORMAT: FULL_MATRIX\nEDGE_WEIGHT_SECTION\n\
+             9999 1 2\n3 9999\n4 5 6\n9999\nEOF"
+        )
+        .unwrap();
+        drop(f);
+
+        let distance = MatrixDistance::load_tsplib(&filepath);
+        assert_eq!(distance.dimension(), 3);
+        assert_eq!(distance.distance(0, 1), 1);
+        assert_eq!(distance.distance(1, 0), 3);
+        assert_eq!(distance.distance(2, 1), 6);
+        assert!(!distance.is_symmetric());
+        std::fs::remove_file(&filepath).unwrap();
+    }
+}
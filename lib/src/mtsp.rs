@@ -0,0 +1,391 @@
+use crate::{
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    edge_constraint::FORBIDDEN_EDGE_COST,
+    lkh::{self, LKHConfig},
+    solution::Solution,
+};
+
+// 複数の巡回路の何を最小化するか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MtspObjective {
+    // 全巡回路の長さの和
+    Total,
+    // 最も長い巡回路の長さ
+    MinMax,
+}
+
+// デポ 1 つと m 人のセールスマンの問題を、1 つの巡回路を求める問題に変換する
+// デポの複製 n, n + 1, ..., n + m - 2 を加え、デポとその複製で区切られた区間をそれぞれのセールスマンの経路とする
+// デポとその複製同士は禁止辺で繋ぎ、どのセールスマンも 1 つ以上の頂点を訪れるようにする
+pub struct MultiDepotDistance<'a, T: DistanceFunction> {
+    ref_distance: &'a T,
+    depot: u32,
+    no_salesman: usize,
+    name: String,
+}
+
+impl<'a, T: DistanceFunction> MultiDepotDistance<'a, T> {
+    pub fn new(ref_distance: &'a T, depot: u32, no_salesman: usize) -> MultiDepotDistance<'a, T> {
+        assert!(no_salesman >= 1);
+        assert!(no_salesman < ref_distance.dimension() as usize);
+        MultiDepotDistance {
+            ref_distance,
+            depot,
+            no_salesman,
+            name: format!("{}.m{}", ref_distance.name(), no_salesman),
+        }
+    }
+
+    fn is_depot(&self, id: u32) -> bool {
+        id == self.depot || id >= self.ref_distance.dimension()
+    }
+
+    fn to_original(&self, id: u32) -> u32 {
+        if self.is_depot(id) {
+            self.depot
+        } else {
+            id
+        }
+    }
+
+    // デポの複製の間に顧客を均等に並べた初期解
+    // 顧客を m 個の空でない組に分け、先頭の len % m 個の組に 1 人ずつ多く割り当てる
+    pub fn initial_solution(&self) -> ArraySolution {
+        let n = self.ref_distance.dimension();
+        let customer_list = (0..n).filter(|&id| id != self.depot).collect::<Vec<_>>();
+        let group_size = customer_list.len() / self.no_salesman;
+        let no_large_group = customer_list.len() % self.no_salesman;
+        let mut vertex_array = vec![];
+        let mut start = 0;
+        for index in 0..self.no_salesman {
+            vertex_array.push(if index == 0 {
+                self.depot
+            } else {
+                n + index as u32 - 1
+            });
+            let end = start + group_size + usize::from(index < no_large_group);
+            vertex_array.extend(&customer_list[start..end]);
+            start = end;
+        }
+        assert_eq!(vertex_array.len(), self.dimension() as usize);
+        ArraySolution::from_array(vertex_array)
+    }
+
+    // 巡回路を m 個の経路に分ける。各経路はデポから始まる
+    pub fn to_routes(&self, solution: &impl Solution) -> Vec<Vec<u32>> {
+        let mut route_list: Vec<Vec<u32>> = vec![];
+        let mut id = self.depot;
+        for _iter in 0..self.dimension() {
+            if self.is_depot(id) {
+                route_list.push(vec![self.depot]);
+            } else {
+                route_list.last_mut().unwrap().push(id);
+            }
+            id = solution.next(id);
+        }
+        route_list
+    }
+}
+
+impl<'a, T: DistanceFunction> DistanceFunction for MultiDepotDistance<'a, T> {
+    fn distance(&self, id1: u32, id2: u32) -> i64 {
+        if id1 == id2 {
+            0
+        } else if self.is_forbidden(id1, id2) {
+            FORBIDDEN_EDGE_COST
+        } else {
+            self.ref_distance
+                .distance(self.to_original(id1), self.to_original(id2))
+        }
+    }
+
+    fn dimension(&self) -> u32 {
+        self.ref_distance.dimension() + self.no_salesman as u32 - 1
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn is_symmetric(&self) -> bool {
+        self.ref_distance.is_symmetric()
+    }
+
+    fn is_forbidden(&self, id1: u32, id2: u32) -> bool {
+        id1 != id2 && self.is_depot(id1) && self.is_depot(id2)
+    }
+
+    fn coordinate(&self, id: u32) -> Option<(i64, i64)> {
+        self.ref_distance.coordinate(self.to_original(id))
+    }
+}
+
+// デポから始まり、デポに戻る経路の長さ
+pub fn route_length(distance: &impl DistanceFunction, route: &[u32]) -> i64 {
+    route
+        .iter()
+        .zip(route.iter().cycle().skip(1))
+        .map(|(&from, &to)| distance.distance(from, to))
+        .sum()
+}
+
+// 経路の集合の目的関数の値
+pub fn evaluate_routes(
+    distance: &impl DistanceFunction,
+    route_list: &[Vec<u32>],
+    objective: MtspObjective,
+) -> i64 {
+    let length_iter = route_list.iter().map(|route| route_length(distance, route));
+    match objective {
+        MtspObjective::Total => length_iter.sum(),
+        MtspObjective::MinMax => length_iter.max().unwrap_or(0),
+    }
+}
+
+// 経路内の 2-opt。経路は短いので全ペアを調べる
+pub(crate) fn optimize_route(distance: &impl DistanceFunction, route: &mut [u32]) {
+    let len = route.len();
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..len {
+            for j in i + 2..len {
+                let (a, b) = (route[i], route[i + 1]);
+                let (c, d) = (route[j], route[(j + 1) % len]);
+                if a == d {
+                    continue;
+                }
+                let gain = distance.distance(a, b) + distance.distance(c, d)
+                    - distance.distance(a, c)
+                    - distance.distance(b, d);
+                if gain > 0 {
+                    route[i + 1..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+}
+
+// route[index] を取り除いたときの長さの減少量
+fn removal_gain(distance: &impl DistanceFunction, route: &[u32], index: usize) -> i64 {
+    let prev = route[index - 1];
+    let next = route[(index + 1) % route.len()];
+    distance.distance(prev, route[index]) + distance.distance(route[index], next)
+        - distance.distance(prev, next)
+}
+
+// id を route[index] と route[index + 1] の間に挿入したときの長さの増加量
+fn insertion_cost(distance: &impl DistanceFunction, route: &[u32], index: usize, id: u32) -> i64 {
+    let prev = route[index];
+    let next = route[(index + 1) % route.len()];
+    distance.distance(prev, id) + distance.distance(id, next) - distance.distance(prev, next)
+}
+
+// 最も長い経路の頂点を他の経路へ移す移動と、他の経路の頂点と交換する移動で、最も長い経路を短くする
+// 最も長い経路が改善するときだけ受理するので、経路長を降順に並べた列が辞書順で単調に減少し、必ず停止する
+pub fn balance_routes(distance: &impl DistanceFunction, route_list: &mut [Vec<u32>]) {
+    for route in route_list.iter_mut() {
+        optimize_route(distance, route);
+    }
+    loop {
+        let length_list = route_list
+            .iter()
+            .map(|route| route_length(distance, route))
+            .collect::<Vec<_>>();
+        let longest = (0..route_list.len())
+            .max_by_key(|&index| length_list[index])
+            .unwrap();
+        let max_length = length_list[longest];
+
+        // (移動後の 2 経路の長い方, 移動先の経路, 取り除く位置, 挿入または交換する位置, 交換か)
+        let mut best: Option<(i64, usize, usize, usize, bool)> = None;
+        let long_route = &route_list[longest];
+        if long_route.len() > 2 {
+            for index in 1..long_route.len() {
+                let id = long_route[index];
+                let new_long = max_length - removal_gain(distance, long_route, index);
+                for (other, route) in route_list.iter().enumerate() {
+                    if other == longest {
+                        continue;
+                    }
+                    for position in 0..route.len() {
+                        let new_length =
+                            length_list[other] + insertion_cost(distance, route, position, id);
+                        let new_max = new_long.max(new_length);
+                        if new_max < best.map_or(max_length, |best| best.0) {
+                            best = Some((new_max, other, index, position, false));
+                        }
+                    }
+                }
+            }
+        }
+        for index in 1..long_route.len() {
+            let id = long_route[index];
+            let long_prev = long_route[index - 1];
+            let long_next = long_route[(index + 1) % long_route.len()];
+            for (other, route) in route_list.iter().enumerate() {
+                if other == longest {
+                    continue;
+                }
+                for position in 1..route.len() {
+                    let other_id = route[position];
+                    let prev = route[position - 1];
+                    let next = route[(position + 1) % route.len()];
+                    let new_long = max_length
+                        - distance.distance(long_prev, id)
+                        - distance.distance(id, long_next)
+                        + distance.distance(long_prev, other_id)
+                        + distance.distance(other_id, long_next);
+                    let new_length = length_list[other]
+                        - distance.distance(prev, other_id)
+                        - distance.distance(other_id, next)
+                        + distance.distance(prev, id)
+                        + distance.distance(id, next);
+                    let new_max = new_long.max(new_length);
+                    if new_max < best.map_or(max_length, |best| best.0) {
+                        best = Some((new_max, other, index, position, true));
+                    }
+                }
+            }
+        }
+
+        let Some((_, other, index, position, exchange)) = best else {
+            break;
+        };
+        if exchange {
+            let id = route_list[longest][index];
+            route_list[longest][index] = route_list[other][position];
+            route_list[other][position] = id;
+        } else {
+            let id = route_list[longest].remove(index);
+            route_list[other].insert(position + 1, id);
+        }
+        optimize_route(distance, &mut route_list[longest]);
+        optimize_route(distance, &mut route_list[other]);
+    }
+}
+
+// デポ depot から出発する no_salesman 人のセールスマンの経路を求める
+// 各経路はデポから始まり、少なくとも 1 つの頂点を訪れる
+// MinMax では、長さの和を LKH で最小化した経路から、最も長い経路を短くするように頂点を移し替える
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    depot: u32,
+    no_salesman: usize,
+    objective: MtspObjective,
+    config: LKHConfig,
+) -> Vec<Vec<u32>> {
+    let multi_depot_distance = MultiDepotDistance::new(distance, depot, no_salesman);
+    let solution = lkh::solve(
+        &multi_depot_distance,
+        multi_depot_distance.initial_solution(),
+        config,
+    );
+    let mut route_list = multi_depot_distance.to_routes(&solution);
+    if objective == MtspObjective::MinMax {
+        balance_routes(distance, &mut route_list);
+    }
+    route_list
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        activation_order::ActivationOrder, improvement_strategy::ImprovementStrategy,
+        lkh::LKHConfig, test_util::random_euclid_distance,
+    };
+
+    use super::{balance_routes, evaluate_routes, solve, MtspObjective, MultiDepotDistance};
+
+    #[test]
+    fn test_initial_solution() {
+        for (size, no_salesman) in [(6, 4), (101, 30), (10, 9)] {
+            let distance = random_euclid_distance(size);
+            let multi_depot_distance = MultiDepotDistance::new(&distance, 0, no_salesman);
+            let route_list =
+                multi_depot_distance.to_routes(&multi_depot_distance.initial_solution());
+
+            // 顧客 size - 1 人を no_salesman 個の組に、大きさの差が 1 以下になるように分ける
+            assert_eq!(route_list.len(), no_salesman);
+            let customer_count = (size - 1) / no_salesman;
+            for route in route_list.iter() {
+                assert_eq!(route[0], 0);
+                assert!(route.len() - 1 == customer_count || route.len() - 1 == customer_count + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mtsp() {
+        const SIZE: usize = 100;
+        const NO_SALESMAN: usize = 4;
+        const DEPOT: u32 = 7;
+
+        let distance = random_euclid_distance(SIZE);
+
+        let mut total_route_list = vec![];
+        for objective in [MtspObjective::Total, MtspObjective::MinMax] {
+            let route_list = solve(
+                &distance,
+                DEPOT,
+                NO_SALESMAN,
+                objective,
+                LKHConfig {
+                    use_neighbor_cache: false,
+                    cache_filepath: PathBuf::new(),
+                    debug: false,
+                    time_ms: 500,
+                    start_kick_step: 5,
+                    kick_step_diff: 5,
+                    end_kick_step: 20,
+                    fail_count_threashold: 10,
+                    max_depth: 5,
+                    use_non_sequential_move: true,
+                    improvement_strategy: ImprovementStrategy::BestImprovement,
+                    activation_order: ActivationOrder::Random,
+                },
+            );
+
+            assert_eq!(route_list.len(), NO_SALESMAN);
+            let mut visited: Vec<u32> = vec![];
+            for route in route_list.iter() {
+                assert_eq!(route[0], DEPOT);
+                assert!(route.len() >= 2);
+                visited.extend(&route[1..]);
+            }
+            visited.sort();
+            assert_eq!(
+                visited,
+                (0..SIZE as u32)
+                    .filter(|&id| id != DEPOT)
+                    .collect::<Vec<_>>()
+            );
+            if objective == MtspObjective::Total {
+                total_route_list = route_list;
+            }
+        }
+
+        // balance_routes は最も長い経路を短くしていくので、同じ経路から始めれば最も長い経路は長くならない
+        let mut balanced = total_route_list.clone();
+        balance_routes(&distance, &mut balanced);
+        let mut visited = balanced
+            .iter()
+            .flat_map(|route| route[1..].iter().cloned())
+            .collect::<Vec<_>>();
+        visited.sort();
+        assert_eq!(
+            visited,
+            (0..SIZE as u32)
+                .filter(|&id| id != DEPOT)
+                .collect::<Vec<_>>()
+        );
+        assert!(
+            evaluate_routes(&distance, &balanced, MtspObjective::MinMax)
+                <= evaluate_routes(&distance, &total_route_list, MtspObjective::MinMax)
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    evaluate::evaluate,
+    lkh::{self, LKHConfig},
+    neighbor_table::NeighborTable,
+    solution::Solution,
+};
+
+pub struct MultiTrialLKHConfig {
+    pub no_trial: usize,
+    // 最初の no_backbone_trial 回の試行で backbone を集める
+    // 以降の試行では候補リストを backbone の辺と近い no_backbone_neighbor 個に絞り、それまでの最良解から探索する
+    pub no_backbone_trial: usize,
+    pub no_backbone_neighbor: usize,
+    pub debug: bool,
+    // time_ms は 1 試行あたりの制限時間
+    pub lkh_config: LKHConfig,
+}
+
+// 全ての解に共通する辺
+fn collect_backbone(solution_list: &[ArraySolution]) -> Vec<(u32, u32)> {
+    let (first, rest) = solution_list.split_first().unwrap();
+    (0..first.len() as u32)
+        .map(|id| (id, first.next(id)))
+        .filter(|&(id1, id2)| {
+            rest.iter()
+                .all(|solution| solution.next(id1) == id2 || solution.prev(id1) == id2)
+        })
+        .collect()
+}
+
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    solution: ArraySolution,
+    config: MultiTrialLKHConfig,
+) -> ArraySolution {
+    let lkh_config = config.lkh_config;
+
+    let neighbor_table = NeighborTable::load_or_build(
+        distance,
+        5,
+        lkh_config.use_neighbor_cache,
+        &lkh_config.cache_filepath,
+    );
+
+    let mut best_eval = evaluate(distance, &solution);
+    let mut best_solution = solution.clone();
+
+    // backbone を集めるための独立な試行
+    let mut trial_solution_list = vec![];
+    for trial in 0..config.no_trial.min(config.no_backbone_trial) {
+        let trial_solution = lkh::solve_with_neighbor_table(
+            distance,
+            &neighbor_table,
+            solution.clone(),
+            lkh_config.clone(),
+        );
+        let eval = evaluate(distance, &trial_solution);
+        if config.debug {
+            eprintln!("trial {}: eval = {}", trial, eval);
+        }
+        if eval < best_eval {
+            best_eval = eval;
+            best_solution.copy_from(&trial_solution);
+        }
+        trial_solution_list.push(trial_solution);
+    }
+
+    if config.no_trial <= config.no_backbone_trial {
+        return best_solution;
+    }
+
+    let neighbor_table = if trial_solution_list.is_empty() {
+        neighbor_table
+    } else {
+        let backbone = collect_backbone(&trial_solution_list);
+        if config.debug {
+            eprintln!("backbone size = {}", backbone.len());
+        }
+        neighbor_table.prioritize(&backbone, config.no_backbone_neighbor)
+    };
+
+    for trial in config.no_backbone_trial..config.no_trial {
+        let trial_solution = lkh::solve_with_neighbor_table(
+            distance,
+            &neighbor_table,
+            best_solution.clone(),
+            lkh_config.clone(),
+        );
+        let eval = evaluate(distance, &trial_solution);
+        if config.debug {
+            eprintln!("trial {}: eval = {}", trial, eval);
+        }
+        if eval < best_eval {
+            best_eval = eval;
+            best_solution.copy_from(&trial_solution);
+        }
+    }
+    best_solution
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        activation_order::ActivationOrder,
+        array_solution::ArraySolution,
+        evaluate::{evaluate, validate},
+        improvement_strategy::ImprovementStrategy,
+        lkh::LKHConfig,
+        test_util::random_euclid_distance,
+    };
+
+    use super::{collect_backbone, solve, MultiTrialLKHConfig};
+
+    #[test]
+    fn test_collect_backbone() {
+        let solution1 = ArraySolution::from_array(vec![0, 1, 2, 3, 4, 5]);
+        let solution2 = ArraySolution::from_array(vec![0, 1, 3, 2, 4, 5]);
+        let mut backbone = collect_backbone(&[solution1, solution2]);
+        backbone.sort();
+        assert_eq!(backbone, vec![(0, 1), (2, 3), (4, 5), (5, 0)]);
+    }
+
+    #[test]
+    fn test_multi_trial_lkh() {
+        const SIZE: usize = 200;
+
+        let distance = random_euclid_distance(SIZE);
+
+        let initial = ArraySolution::new(SIZE);
+        let config = MultiTrialLKHConfig {
+            no_trial: 4,
+            no_backbone_trial: 2,
+            no_backbone_neighbor: 3,
+            debug: false,
+            lkh_config: LKHConfig {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: 200,
+                start_kick_step: 5,
+                kick_step_diff: 5,
+                end_kick_step: 20,
+                fail_count_threashold: 10,
+                max_depth: 5,
+                use_non_sequential_move: true,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+            },
+        };
+        let solution = solve(&distance, initial.clone(), config);
+
+        assert!(validate(&distance, &solution));
+        assert!(evaluate(&distance, &solution) < evaluate(&distance, &initial));
+    }
+}
@@ -1,13 +1,14 @@
 use std::{
+    collections::HashMap,
     fs::File,
     io::{BufWriter, Write},
     path::PathBuf,
-    time::Instant,
 };
 
-use crate::distance::DistanceFunction;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
+use crate::distance::DistanceFunction;
+
 use proconio::input;
 use proconio::source::auto::AutoSource;
 use std::io::Read;
@@ -62,6 +63,168 @@ impl NeighborTable {
         NeighborTable { table }
     }
 
+    // 座標を持つ問題向けに、一様グリッドで近傍を探して O(n) 程度で表を作る
+    // 結果の距離の並びは new と同じになる。座標を持たない問題では None
+    pub fn new_by_grid(
+        distance: &(impl DistanceFunction + std::marker::Sync),
+        neighbor_size: usize,
+    ) -> Option<NeighborTable> {
+        let n = distance.dimension();
+        let coordinate_list = (0..n)
+            .map(|id| distance.coordinate(id))
+            .collect::<Option<Vec<_>>>()?;
+        if n <= 1 {
+            return Some(NeighborTable {
+                table: vec![vec![]; n as usize],
+            });
+        }
+
+        let (min_y, max_y, min_x, max_x) = coordinate_list.iter().fold(
+            (i64::MAX, i64::MIN, i64::MAX, i64::MIN),
+            |(min_y, max_y, min_x, max_x), &(y, x)| {
+                (min_y.min(y), max_y.max(y), min_x.min(x), max_x.max(x))
+            },
+        );
+        // 1 セルあたり平均 2 頂点程度になるようにする
+        let no_cell_side = ((n as f64 / 2.0).sqrt().ceil() as i64).max(1);
+        let cell_size =
+            (((max_y - min_y).max(max_x - min_x) + 1) + no_cell_side - 1) / no_cell_side;
+        let cell_size = cell_size.max(1);
+        let cell_of = |(y, x): (i64, i64)| ((y - min_y) / cell_size, (x - min_x) / cell_size);
+
+        let mut grid = vec![vec![]; (no_cell_side * no_cell_side) as usize];
+        for (id, &coordinate) in coordinate_list.iter().enumerate() {
+            let (cy, cx) = cell_of(coordinate);
+            grid[(cy * no_cell_side + cx) as usize].push(id as u32);
+        }
+
+        let neighbor_size = neighbor_size.min(n as usize - 1);
+        let table = (0..n)
+            .into_par_iter()
+            .map(|i| {
+                let (y, x) = coordinate_list[i as usize];
+                let (cy, cx) = cell_of((y, x));
+                let mut candidate_list = vec![];
+                for r in 0.. {
+                    // 中心から r 周目のセルを走査する
+                    for ny in cy - r..=cy + r {
+                        for nx in cx - r..=cx + r {
+                            if (ny - cy).abs() != r && (nx - cx).abs() != r {
+                                continue;
+                            }
+                            if ny < 0 || nx < 0 || ny >= no_cell_side || nx >= no_cell_side {
+                                continue;
+                            }
+                            for &j in grid[(ny * no_cell_side + nx) as usize].iter() {
+                                if i != j {
+                                    let (jy, jx) = coordinate_list[j as usize];
+                                    let (dy, dx) = (jy - y, jx - x);
+                                    candidate_list.push((dy * dy + dx * dx, j));
+                                }
+                            }
+                        }
+                    }
+                    if r >= no_cell_side {
+                        break;
+                    }
+                    // 未走査のセルの頂点は r * cell_size より遠い
+                    if candidate_list.len() >= neighbor_size {
+                        candidate_list.select_nth_unstable(neighbor_size - 1);
+                        let bound = r * cell_size;
+                        if candidate_list[neighbor_size - 1].0 < bound * bound {
+                            break;
+                        }
+                    }
+                }
+                let mut distance_list = candidate_list
+                    .into_iter()
+                    .map(|(_, j)| (distance.distance(i, j), j))
+                    .collect::<Vec<_>>();
+                distance_list.sort();
+                distance_list
+                    .iter()
+                    .take(neighbor_size)
+                    .map(|(_, index)| *index)
+                    .collect()
+            })
+            .collect();
+        Some(NeighborTable { table })
+    }
+
+    // vertex_map[i] を i と番号付け直した部分問題の表を作る
+    // 部分問題の外の頂点は候補から除き、候補が無くなった頂点には部分問題の巡回路上の前後を入れる
+    pub fn restrict(&self, vertex_map: &[u32]) -> NeighborTable {
+        let local_id = vertex_map
+            .iter()
+            .enumerate()
+            .map(|(local, &id)| (id, local as u32))
+            .collect::<HashMap<_, _>>();
+        let m = vertex_map.len() as u32;
+        let table = vertex_map
+            .iter()
+            .enumerate()
+            .map(|(local, &id)| {
+                let list = self.table[id as usize]
+                    .iter()
+                    .filter_map(|neighbor| local_id.get(neighbor).cloned())
+                    .collect::<Vec<_>>();
+                if list.is_empty() && m > 1 {
+                    let local = local as u32;
+                    let mut list = vec![(local + 1) % m, (local + m - 1) % m];
+                    list.dedup();
+                    list
+                } else {
+                    list
+                }
+            })
+            .collect();
+        NeighborTable { table }
+    }
+
+    // 指定した辺を各頂点の候補リストの先頭に置き、残りは元の候補の先頭 neighbor_size 個に絞った表を作る
+    // 並べ替えるだけでは BestImprovement の探索は変わらないので、候補自体を減らす
+    pub fn prioritize(&self, edge_list: &[(u32, u32)], neighbor_size: usize) -> NeighborTable {
+        let mut table = vec![vec![]; self.table.len()];
+        for &(id1, id2) in edge_list.iter() {
+            table[id1 as usize].push(id2);
+            table[id2 as usize].push(id1);
+        }
+        for (list, original) in table.iter_mut().zip(self.table.iter()) {
+            for &id in original.iter().take(neighbor_size) {
+                if !list.contains(&id) {
+                    list.push(id);
+                }
+            }
+        }
+        NeighborTable { table }
+    }
+
+    // 座標を持つ問題は new_by_grid、持たない問題は new で作る
+    pub fn build(
+        distance: &(impl DistanceFunction + std::marker::Sync),
+        neighbor_size: usize,
+    ) -> NeighborTable {
+        NeighborTable::new_by_grid(distance, neighbor_size)
+            .unwrap_or_else(|| NeighborTable::new(distance, neighbor_size))
+    }
+
+    // use_cache のときはキャッシュがあれば読み込み、無ければ作って保存する
+    pub fn load_or_build(
+        distance: &(impl DistanceFunction + std::marker::Sync),
+        neighbor_size: usize,
+        use_cache: bool,
+        cache_filepath: &PathBuf,
+    ) -> NeighborTable {
+        if use_cache && cache_filepath.exists() {
+            return NeighborTable::load(cache_filepath);
+        }
+        let table = NeighborTable::build(distance, neighbor_size);
+        if use_cache {
+            table.save(cache_filepath);
+        }
+        table
+    }
+
     pub fn neighbor_list(&self, id: u32) -> &Vec<u32> {
         &self.table[id as usize]
     }
@@ -71,12 +234,12 @@ impl NeighborTable {
         let mut writer = BufWriter::new(f);
 
         writer
-            .write(format!("{} {}\n", self.table.len(), self.table[0].len()).as_bytes())
+            .write_all(format!("{} {}\n", self.table.len(), self.table[0].len()).as_bytes())
             .unwrap();
         for row in self.table.iter() {
             let line = row.iter().map(|n| n.to_string()).collect::<Vec<_>>();
-            writer.write(line.join(" ").as_bytes()).unwrap();
-            writer.write("\n".as_bytes()).unwrap();
+            writer.write_all(line.join(" ").as_bytes()).unwrap();
+            writer.write_all("\n".as_bytes()).unwrap();
         }
     }
 
@@ -91,3 +254,73 @@ impl NeighborTable {
         NeighborTable { table }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        distance::DistanceFunction,
+        test_util::{random_euclid_distance, temp_filepath},
+    };
+
+    use super::NeighborTable;
+
+    #[test]
+    fn test_new_by_grid() {
+        const SIZE: usize = 300;
+
+        let distance = random_euclid_distance(SIZE);
+
+        let expected = NeighborTable::new(&distance, 5);
+        let actual = NeighborTable::new_by_grid(&distance, 5).unwrap();
+        for id in 0..SIZE as u32 {
+            let to_distance = |table: &NeighborTable| {
+                table
+                    .neighbor_list(id)
+                    .iter()
+                    .map(|&j| distance.distance(id, j))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(to_distance(&actual), to_distance(&expected));
+        }
+    }
+
+    #[test]
+    fn test_load_or_build() {
+        const SIZE: usize = 50;
+
+        let distance = random_euclid_distance(SIZE);
+        let filepath = temp_filepath("neighbor_table_cache.txt");
+        let _ = std::fs::remove_file(&filepath);
+
+        // 初回は作って保存し、2 回目はキャッシュから同じ表を読み込む
+        let built = NeighborTable::load_or_build(&distance, 5, true, &filepath);
+        assert!(filepath.exists());
+        let loaded = NeighborTable::load_or_build(&distance, 5, true, &filepath);
+        assert_eq!(loaded.table, built.table);
+
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn test_restrict() {
+        let table = NeighborTable {
+            table: vec![vec![1, 2], vec![0, 3], vec![3, 0], vec![2, 1]],
+        };
+        let restricted = table.restrict(&[2, 3, 1]);
+        assert_eq!(restricted.neighbor_list(0), &vec![1]);
+        assert_eq!(restricted.neighbor_list(1), &vec![0, 2]);
+        assert_eq!(restricted.neighbor_list(2), &vec![1]);
+    }
+
+    #[test]
+    fn test_prioritize() {
+        let table = NeighborTable {
+            table: vec![vec![1, 2, 3], vec![0, 2, 3], vec![3, 1, 0], vec![2, 1, 0]],
+        };
+        let prioritized = table.prioritize(&[(0, 3)], 1);
+        assert_eq!(prioritized.neighbor_list(0), &vec![3, 1]);
+        assert_eq!(prioritized.neighbor_list(1), &vec![0]);
+        assert_eq!(prioritized.neighbor_list(2), &vec![3]);
+        assert_eq!(prioritized.neighbor_list(3), &vec![0, 2]);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    edge_constraint::FIXED_EDGE_COST,
+    lkh::{self, LKHConfig},
+    solution::Solution,
+};
+
+// 巡回路ではなくパスを求めるときの端点の条件
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathMode {
+    // 始点も終点も自由
+    Free,
+    // 始点だけ固定
+    FixedStart(u32),
+    // 始点と終点を固定
+    FixedEnds(u32, u32),
+}
+
+// パスを求める問題を、巡回路を求める問題に変換する
+// Free と FixedStart では全頂点とコスト 0 で繋がるダミー頂点 n を加え、巡回路からダミー頂点を除いたものをパスとする
+// FixedStart ではダミー頂点と始点、FixedEnds では終点と始点を必ず使う辺で繋ぐ
+pub struct PathDistance<'a, T: DistanceFunction> {
+    ref_distance: &'a T,
+    mode: PathMode,
+    name: String,
+}
+
+impl<'a, T: DistanceFunction> PathDistance<'a, T> {
+    pub fn new(ref_distance: &'a T, mode: PathMode) -> PathDistance<'a, T> {
+        if let PathMode::FixedEnds(start, end) = mode {
+            assert_ne!(start, end);
+        }
+        PathDistance {
+            ref_distance,
+            mode,
+            name: format!("{}.path", ref_distance.name()),
+        }
+    }
+
+    fn has_dummy(&self) -> bool {
+        !matches!(self.mode, PathMode::FixedEnds(_, _))
+    }
+
+    // 必ず使う辺。Free では無い
+    fn forced_edge(&self) -> Option<(u32, u32)> {
+        match self.mode {
+            PathMode::Free => None,
+            PathMode::FixedStart(start) => Some((self.ref_distance.dimension(), start)),
+            PathMode::FixedEnds(start, end) => Some((end, start)),
+        }
+    }
+
+    // 必ず使う辺を含む初期解
+    pub fn initial_solution(&self) -> ArraySolution {
+        let n = self.ref_distance.dimension();
+        let (first, last) = match self.mode {
+            PathMode::Free => (None, None),
+            PathMode::FixedStart(start) => (Some(start), None),
+            PathMode::FixedEnds(start, end) => (Some(start), Some(end)),
+        };
+        let mut vertex_array = vec![];
+        if self.has_dummy() {
+            vertex_array.push(n);
+        }
+        vertex_array.extend(first);
+        vertex_array.extend((0..n).filter(|&id| Some(id) != first && Some(id) != last));
+        vertex_array.extend(last);
+        ArraySolution::from_array(vertex_array)
+    }
+
+    // 巡回路を、元の問題のパスの頂点列に戻す
+    pub fn to_path(&self, solution: &impl Solution) -> Vec<u32> {
+        let n = self.ref_distance.dimension();
+        let (start, forward) = match self.mode {
+            PathMode::Free => (solution.next(n), true),
+            PathMode::FixedStart(start) => (start, solution.prev(start) == n),
+            PathMode::FixedEnds(start, end) => (start, solution.prev(start) == end),
+        };
+        let mut path = Vec::with_capacity(n as usize);
+        let mut id = start;
+        for _iter in 0..n {
+            path.push(id);
+            id = if forward {
+                solution.next(id)
+            } else {
+                solution.prev(id)
+            };
+        }
+        path
+    }
+}
+
+impl<'a, T: DistanceFunction> DistanceFunction for PathDistance<'a, T> {
+    fn distance(&self, id1: u32, id2: u32) -> i64 {
+        let n = self.ref_distance.dimension();
+        if id1 == id2 {
+            0
+        } else if self.is_fixed(id1, id2) {
+            FIXED_EDGE_COST
+        } else if id1 == n || id2 == n {
+            0
+        } else {
+            self.ref_distance.distance(id1, id2)
+        }
+    }
+
+    fn dimension(&self) -> u32 {
+        self.ref_distance.dimension() + if self.has_dummy() { 1 } else { 0 }
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn is_symmetric(&self) -> bool {
+        self.ref_distance.is_symmetric()
+    }
+
+    fn is_fixed(&self, id1: u32, id2: u32) -> bool {
+        self.forced_edge()
+            .is_some_and(|(a, b)| (id1, id2) == (a, b) || (id1, id2) == (b, a))
+    }
+
+    fn fixed_edge_list(&self) -> Vec<(u32, u32)> {
+        self.forced_edge().into_iter().collect()
+    }
+}
+
+// パスの長さ
+pub fn evaluate_path(distance: &impl DistanceFunction, path: &[u32]) -> i64 {
+    path.windows(2)
+        .map(|pair| distance.distance(pair[0], pair[1]))
+        .sum()
+}
+
+// 端点の条件を満たすパスを LKH で求め、始点から順に並べた頂点列を返す
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    mode: PathMode,
+    config: LKHConfig,
+) -> Vec<u32> {
+    let path_distance = PathDistance::new(distance, mode);
+    let solution = lkh::solve(&path_distance, path_distance.initial_solution(), config);
+    path_distance.to_path(&solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        activation_order::ActivationOrder, evaluate::validate, exact,
+        improvement_strategy::ImprovementStrategy, lkh::LKHConfig,
+        test_util::random_euclid_distance,
+    };
+
+    use super::{evaluate_path, solve, PathDistance, PathMode};
+
+    #[test]
+    fn test_open_path() {
+        const SIZE: usize = 12;
+
+        let distance = random_euclid_distance(SIZE);
+
+        for mode in [
+            PathMode::Free,
+            PathMode::FixedStart(3),
+            PathMode::FixedEnds(3, 7),
+        ] {
+            let path = solve(
+                &distance,
+                mode,
+                LKHConfig {
+                    use_neighbor_cache: false,
+                    cache_filepath: PathBuf::new(),
+                    debug: false,
+                    time_ms: 300,
+                    start_kick_step: 5,
+                    kick_step_diff: 5,
+                    end_kick_step: 10,
+                    fail_count_threashold: 10,
+                    max_depth: 5,
+                    use_non_sequential_move: true,
+                    improvement_strategy: ImprovementStrategy::BestImprovement,
+                    activation_order: ActivationOrder::Random,
+                },
+            );
+
+            let mut sorted = path.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..SIZE as u32).collect::<Vec<_>>());
+            match mode {
+                PathMode::Free => {}
+                PathMode::FixedStart(start) => assert_eq!(path[0], start),
+                PathMode::FixedEnds(start, end) => {
+                    assert_eq!(path[0], start);
+                    assert_eq!(path[SIZE - 1], end);
+                }
+            }
+
+            let path_distance = PathDistance::new(&distance, mode);
+            assert!(validate(&path_distance, &path_distance.initial_solution()));
+            let (optimal, _) = exact::solve_dp(&path_distance);
+            assert!(validate(&path_distance, &optimal));
+            assert_eq!(
+                evaluate_path(&distance, &path),
+                evaluate_path(&distance, &path_distance.to_path(&optimal))
+            );
+        }
+    }
+}
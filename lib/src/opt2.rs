@@ -1,8 +1,15 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Instant};
 
 use crate::{
-    array_solution::ArraySolution, distance::DistanceFunction, evaluate::evaluate, intset::IntSet,
-    neighbor_table::NeighborTable, solution::Solution,
+    activation_order::{ActivationOrder, ActiveSet},
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    edge_constraint::is_allowed_change,
+    evaluate::evaluate,
+    improvement_strategy::{ImprovementSelector, ImprovementStrategy},
+    neighbor_table::NeighborTable,
+    solution::Solution,
+    stop_reason::{StopReason, TIME_CHECK_INTERVAL},
     two_level_tree_solution::TwoLeveltreeSolution,
 };
 
@@ -10,40 +17,58 @@ pub struct Opt2Config {
     pub use_neighbor_cache: bool,
     pub cache_filepath: PathBuf,
     pub debug: bool,
+    // None なら制限なし
+    pub time_ms: Option<u128>,
+    pub max_iteration: Option<usize>,
+    pub improvement_strategy: ImprovementStrategy,
+    pub activation_order: ActivationOrder,
 }
 
 pub fn solve(
     distance: &(impl DistanceFunction + std::marker::Sync),
     solution: ArraySolution,
     config: Opt2Config,
-) -> ArraySolution {
+) -> (ArraySolution, StopReason) {
+    assert!(
+        distance.is_symmetric(),
+        "2-opt requires a symmetric distance; use SymmetricTransform"
+    );
+    let neighbor_table = NeighborTable::load_or_build(
+        distance,
+        5,
+        config.use_neighbor_cache,
+        &config.cache_filepath,
+    );
+    solve_with_neighbor_table(distance, &neighbor_table, solution, config)
+}
+
+// 近傍リストを使い回したい呼び出し元 (GA の初期集団生成など) 向け
+pub(crate) fn solve_with_neighbor_table(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    neighbor_table: &NeighborTable,
+    solution: ArraySolution,
+    config: Opt2Config,
+) -> (ArraySolution, StopReason) {
     let n = solution.len();
 
-    let mut tlt = TwoLeveltreeSolution::<1000>::new(&solution);
+    let start = Instant::now();
 
-    let neighbor_table = if config.use_neighbor_cache && config.cache_filepath.exists() {
-        NeighborTable::load(&config.cache_filepath)
-    } else {
-        let table = NeighborTable::new(distance, 5);
-        if config.use_neighbor_cache {
-            table.save(&config.cache_filepath);
-        }
-        table
-    };
+    let mut tlt = TwoLeveltreeSolution::<1000>::new(&solution);
 
     let mut rng = rand::thread_rng();
 
-    let mut dlb = IntSet::new(n);
+    let mut dlb = ActiveSet::new(n, config.activation_order);
     dlb.set_all();
 
     let mut eval = evaluate(distance, &tlt);
 
+    let mut stop_reason = StopReason::LocalOptimum;
+
     for iter in 0.. {
-        let a = dlb.random_select(&mut rng);
+        let a = dlb.select(&mut rng);
         let b = tlt.next(a);
 
-        let mut best_gain = 0;
-        let mut best_c = 0;
+        let mut selector = ImprovementSelector::new(config.improvement_strategy);
 
         for c in neighbor_table.neighbor_list(a) {
             let c = *c;
@@ -51,16 +76,18 @@ pub fn solve(
             if a == d || b == c || b == d {
                 continue;
             }
+            if !is_allowed_change(distance, &[(a, b), (c, d)], &[(a, c), (b, d)]) {
+                continue;
+            }
 
             let gain = distance.distance(a, b) + distance.distance(c, d)
                 - distance.distance(a, c)
                 - distance.distance(b, d);
-            if gain > best_gain {
-                best_gain = gain;
-                best_c = c;
+            if selector.update(gain, c) {
+                break;
             }
         }
-        if best_gain > 0 {
+        if let Some((best_gain, best_c)) = selector.into_best() {
             dlb.push(a);
             dlb.push(b);
             dlb.push(best_c);
@@ -80,6 +107,21 @@ pub fn solve(
         if dlb.is_empty() {
             break;
         }
+        if config
+            .max_iteration
+            .is_some_and(|max_iteration| iter + 1 >= max_iteration)
+        {
+            stop_reason = StopReason::IterationLimit;
+            break;
+        }
+        if iter % TIME_CHECK_INTERVAL == 0
+            && config
+                .time_ms
+                .is_some_and(|time_ms| start.elapsed().as_millis() > time_ms)
+        {
+            stop_reason = StopReason::TimeLimit;
+            break;
+        }
     }
-    tlt.to_array_solution()
+    (tlt.to_array_solution(), stop_reason)
 }
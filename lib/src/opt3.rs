@@ -1,23 +1,115 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Instant};
 
 use crate::{
-    array_solution::ArraySolution, bitset::BitSet, distance::DistanceFunction, evaluate::evaluate,
-    intset::IntSet, neighbor_table::NeighborTable, solution::Solution,
+    activation_order::{ActivationOrder, ActiveSet},
+    array_solution::ArraySolution,
+    bitset::BitSet,
+    distance::DistanceFunction,
+    edge_constraint::is_allowed_change,
+    evaluate::evaluate,
+    improvement_strategy::{ImprovementSelector, ImprovementStrategy},
+    neighbor_table::NeighborTable,
+    solution::Solution,
+    stop_reason::{StopReason, TIME_CHECK_INTERVAL},
     two_level_tree_solution::TwoLeveltreeSolution,
 };
 
 #[derive(Debug)]
 enum NeighborPattern {
-    None,
     Pat1((u32, u32)),
     Pat2((u32, u32), (u32, u32)),
     Pat3((u32, u32), (u32, u32), (u32, u32)),
 }
 
+impl NeighborPattern {
+    fn swap_list(&self) -> Vec<(u32, u32)> {
+        match *self {
+            NeighborPattern::Pat1(s1) => vec![s1],
+            NeighborPattern::Pat2(s1, s2) => vec![s1, s2],
+            NeighborPattern::Pat3(s1, s2, s3) => vec![s1, s2, s3],
+        }
+    }
+}
+
+// (a, b), (c, d), (e, f) が巡回路上でこの順に並んでいるときの繋ぎ替え
+// case ごとのゲインと、繋ぎ替えを実現する swap の手順を返す
+// 消す辺と張る辺の組を allowed が許さなければ None
+fn reconnect(
+    case: usize,
+    dist: &impl Fn(u32, u32) -> i64,
+    allowed: &impl Fn(&[(u32, u32)], &[(u32, u32)]) -> bool,
+    (a, b, c, d, e, f): (u32, u32, u32, u32, u32, u32),
+) -> Option<(i64, NeighborPattern)> {
+    let change = |removed: &[(u32, u32)], added: &[(u32, u32)], pattern| {
+        if !allowed(removed, added) {
+            return None;
+        }
+        let gain = removed.iter().map(|&(i1, i2)| dist(i1, i2)).sum::<i64>()
+            - added.iter().map(|&(i1, i2)| dist(i1, i2)).sum::<i64>();
+        Some((gain, pattern))
+    };
+    match case {
+        // [(a, b), (c, d)] -> [(a, c), (b, d)]
+        1 => change(
+            &[(a, b), (c, d)],
+            &[(a, c), (b, d)],
+            NeighborPattern::Pat1((b, c)),
+        ),
+        // [(c, d), (e, f)] -> [(c, e), (d, f)]
+        2 => change(
+            &[(c, d), (e, f)],
+            &[(c, e), (d, f)],
+            NeighborPattern::Pat1((d, e)),
+        ),
+        // [(a, b), (e, f)] -> [(a, e), (b, f)]
+        3 => change(
+            &[(a, b), (e, f)],
+            &[(a, e), (b, f)],
+            NeighborPattern::Pat1((f, a)),
+        ),
+        // [(a, b), (c, d), (e, f)] -> [(a, c), (b, e), (d, f)]
+        // [(a, b), (c, d), (e, f)] -> [(a, c), (b, d), (e, f)] -> [(a, c), (b, e), (d, f)]
+        4 => change(
+            &[(a, b), (c, d), (e, f)],
+            &[(a, c), (b, e), (d, f)],
+            NeighborPattern::Pat2((b, c), (d, e)),
+        ),
+        // [(a, b), (c, d), (e, f)] -> [(a, e), (d, b), (c, f)]
+        // [(a, b), (c, d), (e, f)] -> [(a, e), (d, c), (b, f)] -> [(a, e), (d, b), (c, f)]
+        5 => change(
+            &[(a, b), (c, d), (e, f)],
+            &[(a, e), (d, b), (c, f)],
+            NeighborPattern::Pat2((b, e), (c, b)),
+        ),
+        // [(a, b), (c, d), (e, f)] -> [(a, d), (e, c), (b, f)]
+        // [(a, b), (c, d), (e, f)] -> [(a, e), (d, c), (b, f)] -> [(a, d), (e, c), (b, f)]
+        6 => change(
+            &[(a, b), (c, d), (e, f)],
+            &[(a, d), (e, c), (b, f)],
+            NeighborPattern::Pat2((b, e), (e, d)),
+        ),
+        // [(a, b), (c, d), (e, f)] -> [(a, d), (e, b), (c, f)]
+        // [(a, b), (c, d), (e, f)] -> [(a, e), (d, c), (b, f)] -> [(a, d), (e, c), (b, f)] -> [(a, d), (e, b), (c, f)]
+        7 => change(
+            &[(a, b), (c, d), (e, f)],
+            &[(a, d), (e, b), (c, f)],
+            NeighborPattern::Pat3((b, e), (e, d), (c, b)),
+        ),
+        _ => unreachable!(),
+    }
+}
+
 pub struct Opt3Config {
     pub use_neighbor_cache: bool,
     pub cache_filepath: PathBuf,
     pub debug: bool,
+    // None なら制限なし
+    pub time_ms: Option<u128>,
+    pub max_iteration: Option<usize>,
+    pub improvement_strategy: ImprovementStrategy,
+    pub activation_order: ActivationOrder,
+    // or-opt で動かす部分列の最大長。0 なら or-opt を行わない
+    pub or_opt_max_len: usize,
 }
 
 // https://en.wikipedia.org/wiki/3-opt
@@ -25,41 +117,49 @@ pub fn solve(
     distance: &(impl DistanceFunction + std::marker::Sync),
     solution: ArraySolution,
     config: Opt3Config,
-) -> ArraySolution {
+) -> (ArraySolution, StopReason) {
+    assert!(
+        distance.is_symmetric(),
+        "3-opt requires a symmetric distance; use SymmetricTransform"
+    );
     let n = solution.len();
 
+    let start = Instant::now();
+
     let mut tlt = TwoLeveltreeSolution::<1000>::new(&solution);
 
-    let neighbor_table = if config.use_neighbor_cache && config.cache_filepath.exists() {
-        NeighborTable::load(&config.cache_filepath)
-    } else {
-        let table = NeighborTable::new(distance, 5);
-        if config.use_neighbor_cache {
-            table.save(&config.cache_filepath);
-        }
-        table
-    };
+    let neighbor_table = NeighborTable::load_or_build(
+        distance,
+        5,
+        config.use_neighbor_cache,
+        &config.cache_filepath,
+    );
 
     let mut rng = rand::thread_rng();
 
-    let mut dlb = IntSet::new(n);
+    let mut dlb = ActiveSet::new(n, config.activation_order);
     dlb.set_all();
 
     let mut eval = evaluate(distance, &tlt);
     let mut selected = BitSet::new(n);
 
+    let mut stop_reason = StopReason::LocalOptimum;
+
+    let dist = |i1, i2| distance.distance(i1, i2);
+    let allowed =
+        |removed: &[(u32, u32)], added: &[(u32, u32)]| is_allowed_change(distance, removed, added);
+
     for iter in 0.. {
-        let a = dlb.random_select(&mut rng);
+        let a = dlb.select(&mut rng);
 
         selected.clear_all();
 
-        let mut best_gain = 0;
-        let mut best_pat = NeighborPattern::None;
+        let mut selector = ImprovementSelector::new(config.improvement_strategy);
 
         let a_next = tlt.next(a);
         let a_prev = tlt.prev(a);
 
-        for (a, b) in [(a_prev, a), (a, a_next)] {
+        'search: for (a, b) in [(a_prev, a), (a, a_next)] {
             selected.set(a);
             selected.set(b);
 
@@ -86,64 +186,21 @@ pub fn solve(
                             selected.set(e);
                             selected.set(f);
 
-                            let dist = |i1, i2| distance.distance(i1, i2);
-
                             let (ca, cb, cc, cd, ce, cf) = if tlt.between(c, a, e) {
                                 (a, b, c, d, e, f)
                             } else {
                                 (a, b, e, f, c, d)
                             };
 
-                            // case 1
-                            // [(a, b), (c, d)] -> [(a, c), (b, d)]
-                            let gain1 = dist(ca, cb) + dist(cc, cd) - dist(ca, cc) - dist(cb, cd);
-                            if gain1 > best_gain {
-                                best_gain = gain1;
-                                best_pat = NeighborPattern::Pat1((cb, cc))
-                            }
-
-                            // case 2
-                            // [(c, d), (e, f)] -> [(c, e), (d, f)]
-                            let gain2 = dist(cc, cd) + dist(ce, cf) - dist(cc, ce) - dist(cd, cf);
-                            if gain2 > best_gain {
-                                best_gain = gain2;
-                                best_pat = NeighborPattern::Pat1((cd, ce));
-                            }
-
-                            // case 4
-                            // [(a, b), (c, d), (e, f)] -> [(a, c), (b, e), (d, f)]
-                            // [(a, b), (c, d), (e, f)] -> [(a, c), (b, d), (e, f)] -> [(a, c), (b, e), (d, f)]
-                            let gain4 = dist(ca, cb) + dist(cc, cd) + dist(ce, cf)
-                                - dist(ca, cc)
-                                - dist(cb, ce)
-                                - dist(cd, cf);
-                            if gain4 > best_gain {
-                                best_gain = gain4;
-                                best_pat = NeighborPattern::Pat2((cb, cc), (cd, ce));
-                            }
-
-                            // case 6
-                            // [(a, b), (c, d), (e, f)] -> [(a, d), (e, c), (b, f)]
-                            // [(a, b), (c, d), (e, f)] -> [(a, e), (d, c), (b, f)] -> [(a, d), (e, c), (b, f)]
-                            let gain6 = dist(ca, cb) + dist(cc, cd) + dist(ce, cf)
-                                - dist(ca, cd)
-                                - dist(ce, cc)
-                                - dist(cb, cf);
-                            if gain6 > best_gain {
-                                best_gain = gain6;
-                                best_pat = NeighborPattern::Pat2((cb, ce), (ce, cd));
-                            }
-
-                            // case 7
-                            // [(a, b), (c, d), (e, f)] -> [(a, d), (e, b), (c, f)]
-                            // [(a, b), (c, d), (e, f)] -> [(a, e), (d, c), (b, f)] -> [(a, d), (e, c), (b, f)] -> [(a, d), (e, b), (c, f)]
-                            let gain7 = dist(ca, cb) + dist(cc, cd) + dist(ce, cf)
-                                - dist(ca, cd)
-                                - dist(ce, cb)
-                                - dist(cc, cf);
-                            if gain7 > best_gain {
-                                best_gain = gain7;
-                                best_pat = NeighborPattern::Pat3((cb, ce), (ce, cd), (cc, cb));
+                            for case in [1, 2, 4, 6, 7] {
+                                let Some((gain, pattern)) =
+                                    reconnect(case, &dist, &allowed, (ca, cb, cc, cd, ce, cf))
+                                else {
+                                    continue;
+                                };
+                                if selector.update(gain, pattern) {
+                                    break 'search;
+                                }
                             }
 
                             selected.clear(e);
@@ -179,32 +236,21 @@ pub fn solve(
                             selected.set(c);
                             selected.set(d);
 
-                            let dist = |i1, i2| distance.distance(i1, i2);
-
                             let (ca, cb, cc, cd, ce, cf) = if tlt.between(c, a, e) {
                                 (a, b, c, d, e, f)
                             } else {
                                 (a, b, e, f, c, d)
                             };
 
-                            // case 3
-                            // [(a, b), (e, f)] -> [(a, e), (b, f)]
-                            let gain3 = dist(ca, cb) + dist(ce, cf) - dist(ca, ce) - dist(cb, cf);
-                            if gain3 > best_gain {
-                                best_gain = gain3;
-                                best_pat = NeighborPattern::Pat1((cf, ca));
-                            }
-
-                            // case 5
-                            // [(a, b), (c, d), (e, f)] -> [(a, e), (d, b), (c, f)]
-                            // [(a, b), (c, d), (e, f)] -> [(a, e), (d, c), (b, f)] -> [(a, e), (d, b), (c, f)]
-                            let gain5 = dist(ca, cb) + dist(cc, cd) + dist(ce, cf)
-                                - dist(ca, ce)
-                                - dist(cd, cb)
-                                - dist(cc, cf);
-                            if gain5 > best_gain {
-                                best_gain = gain5;
-                                best_pat = NeighborPattern::Pat2((cb, ce), (cc, cb));
+                            for case in [3, 5] {
+                                let Some((gain, pattern)) =
+                                    reconnect(case, &dist, &allowed, (ca, cb, cc, cd, ce, cf))
+                                else {
+                                    continue;
+                                };
+                                if selector.update(gain, pattern) {
+                                    break 'search;
+                                }
                             }
                             selected.clear(c);
                             selected.clear(d);
@@ -219,32 +265,75 @@ pub fn solve(
             selected.clear(b);
         }
 
-        // swap
-        match best_pat {
-            NeighborPattern::None => {
-                dlb.remove(a);
+        // or-opt
+        // a を端に含む長さ or_opt_max_len 以下の部分列 [s1, ..., s2] を取り出して、
+        // 端点の近くにある別の辺 (e, f) の間に、向きを保ったまま、または反転して挿入する
+        'or_opt: for len in 1..=config.or_opt_max_len.min(n.saturating_sub(4)) {
+            if selector.is_satisfied() {
+                break;
             }
-            NeighborPattern::Pat1((i1, i2)) => {
-                tlt.swap(i1, i2);
-                for i in [i1, i2] {
-                    dlb.push(i);
-                }
-                eval -= best_gain;
+
+            let mut s_front = a;
+            let mut s_back = a;
+            for _iter in 1..len {
+                s_front = tlt.prev(s_front);
+                s_back = tlt.next(s_back);
             }
-            NeighborPattern::Pat2((i1, i2), (i3, i4)) => {
-                tlt.swap(i1, i2);
-                tlt.swap(i3, i4);
-                for i in [i1, i2, i3, i4] {
-                    dlb.push(i);
+            let segment_list = if len == 1 {
+                vec![(a, a)]
+            } else {
+                vec![(a, s_back), (s_front, a)]
+            };
+
+            for (s1, s2) in segment_list.into_iter() {
+                let p = tlt.prev(s1);
+                let q = tlt.next(s2);
+
+                selected.clear_all();
+                selected.set(p);
+                selected.set(q);
+                let mut id = s1;
+                for _iter in 0..len {
+                    selected.set(id);
+                    id = tlt.next(id);
                 }
-                eval -= best_gain;
+
+                for g in neighbor_table
+                    .neighbor_list(s1)
+                    .iter()
+                    .chain(neighbor_table.neighbor_list(s2).iter())
+                {
+                    for (e, f) in [(tlt.prev(*g), *g), (*g, tlt.next(*g))] {
+                        if selected.test(e) || selected.test(f) {
+                            continue;
+                        }
+                        // (p, s1), (s2, q), (e, f) は常にこの順で巡回路上に並ぶ
+                        // case 7 が部分列をそのまま、case 6 が部分列を反転して挿入する繋ぎ替えになる
+                        for case in [7, 6] {
+                            let Some((gain, pattern)) =
+                                reconnect(case, &dist, &allowed, (p, s1, s2, q, e, f))
+                            else {
+                                continue;
+                            };
+                            if selector.update(gain, pattern) {
+                                break 'or_opt;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // swap
+        match selector.into_best() {
+            None => {
+                dlb.remove(a);
             }
-            NeighborPattern::Pat3((i1, i2), (i3, i4), (i5, i6)) => {
-                tlt.swap(i1, i2);
-                tlt.swap(i3, i4);
-                tlt.swap(i5, i6);
-                for i in [i1, i2, i3, i4, i5, i6] {
-                    dlb.push(i);
+            Some((best_gain, pattern)) => {
+                for (i1, i2) in pattern.swap_list() {
+                    tlt.swap(i1, i2);
+                    dlb.push(i1);
+                    dlb.push(i2);
                 }
                 eval -= best_gain;
             }
@@ -257,6 +346,155 @@ pub fn solve(
         if dlb.is_empty() {
             break;
         }
+        if config
+            .max_iteration
+            .is_some_and(|max_iteration| iter + 1 >= max_iteration)
+        {
+            stop_reason = StopReason::IterationLimit;
+            break;
+        }
+        if iter % TIME_CHECK_INTERVAL == 0
+            && config
+                .time_ms
+                .is_some_and(|time_ms| start.elapsed().as_millis() > time_ms)
+        {
+            stop_reason = StopReason::TimeLimit;
+            break;
+        }
+    }
+    (tlt.to_array_solution(), stop_reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{seq::SliceRandom, thread_rng, Rng};
+
+    use crate::{
+        array_solution::ArraySolution, distance::DistanceFunction, evaluate::evaluate,
+        matrix_distance::MatrixDistance, solution::Solution,
+    };
+
+    use super::reconnect;
+
+    // 対称になるように、上三角の重みだけを使う
+    fn random_distance(n: usize) -> MatrixDistance {
+        let mut rng = thread_rng();
+        let weight = (0..n * n)
+            .map(|_| rng.gen_range(1..100))
+            .collect::<Vec<i64>>();
+        let matrix = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| {
+                        if i == j {
+                            0
+                        } else {
+                            weight[i.min(j) * n + i.max(j)]
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        MatrixDistance::new(matrix, "random".to_string())
+    }
+
+    fn random_solution(n: usize) -> ArraySolution {
+        let mut content = (0..n as u32).collect::<Vec<_>>();
+        content.shuffle(&mut thread_rng());
+        ArraySolution::from_array(content)
+    }
+
+    #[test]
+    fn test_reconnect_cases() {
+        const SIZE: usize = 12;
+        let mut rng = thread_rng();
+
+        for _iter in 0..200 {
+            let distance = random_distance(SIZE);
+            let dist = |i1, i2| distance.distance(i1, i2);
+            let solution = random_solution(SIZE);
+            let before = evaluate(&distance, &solution);
+
+            // 端点が重複しないように、巡回路上でこの順に並ぶ 3 辺を選ぶ
+            let i = rng.gen_range(0..SIZE);
+            let j = i + rng.gen_range(2..SIZE - 4);
+            let k = j + rng.gen_range(2..SIZE + i - j - 1);
+            let edge = |index: usize| {
+                let id = solution.id_of(index % SIZE);
+                (id, solution.next(id))
+            };
+            let ((a, b), (c, d), (e, f)) = (edge(i), edge(j), edge(k));
+
+            for case in 1..=7 {
+                let (gain, pattern) =
+                    reconnect(case, &dist, &|_, _| true, (a, b, c, d, e, f)).unwrap();
+                let mut after_solution = solution.clone();
+                for (from, to) in pattern.swap_list() {
+                    after_solution.swap(from, to);
+                }
+                assert_eq!(
+                    before - gain,
+                    evaluate(&distance, &after_solution),
+                    "case {}",
+                    case
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_or_opt() {
+        const SIZE: usize = 12;
+        let mut rng = thread_rng();
+
+        for _iter in 0..200 {
+            let distance = random_distance(SIZE);
+            let dist = |i1, i2| distance.distance(i1, i2);
+            let solution = random_solution(SIZE);
+            let before = evaluate(&distance, &solution);
+
+            // [p, s1, ..., s2, q, ..., e, f, ...] の順に並ぶ
+            let len = rng.gen_range(1..=3);
+            let start = rng.gen_range(0..SIZE);
+            let offset = rng.gen_range(len + 1..SIZE - 2);
+            let id = |index: usize| solution.id_of(index % SIZE);
+            let (p, s1, s2, q) = (
+                id(start),
+                id(start + 1),
+                id(start + len),
+                id(start + len + 1),
+            );
+            let (e, f) = (id(start + offset), id(start + offset + 1));
+
+            // 部分列を実際に配列上で動かした巡回路
+            let array = (0..SIZE).map(|index| id(start + index)).collect::<Vec<_>>();
+            let segment = array[1..=len].to_vec();
+            for reversed in [false, true] {
+                let mut expected = array.clone();
+                expected.drain(1..=len);
+                let insert_pos = expected.iter().position(|v| *v == e).unwrap() + 1;
+                let mut inserted = segment.clone();
+                if reversed {
+                    inserted.reverse();
+                }
+                expected.splice(insert_pos..insert_pos, inserted);
+                let expected = ArraySolution::from_array(expected);
+
+                let case = if reversed { 6 } else { 7 };
+                let (gain, pattern) =
+                    reconnect(case, &dist, &|_, _| true, (p, s1, s2, q, e, f)).unwrap();
+                let mut after_solution = solution.clone();
+                for (from, to) in pattern.swap_list() {
+                    after_solution.swap(from, to);
+                }
+
+                assert_eq!(before - gain, evaluate(&distance, &expected));
+                for v in 0..SIZE as u32 {
+                    let neighbor = [after_solution.prev(v), after_solution.next(v)];
+                    assert!(neighbor.contains(&expected.next(v)));
+                    assert!(neighbor.contains(&expected.prev(v)));
+                }
+            }
+        }
     }
-    tlt.to_array_solution()
 }
@@ -0,0 +1,130 @@
+use std::time::Instant;
+
+use rayon::prelude::*;
+
+use crate::{
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    evaluate::evaluate,
+    lkh::{self, LKHConfig, LKHState},
+    neighbor_table::NeighborTable,
+};
+
+pub struct ParallelLKHConfig {
+    // 同時に走らせる探索の数
+    pub no_search: usize,
+    // 全体の制限時間
+    pub time_ms: u128,
+    // この間隔ごとに全探索の最良解を集め、それより悪い探索をそこへ乗り換えさせる
+    pub sync_interval_ms: u128,
+    pub debug: bool,
+    // time_ms は sync_interval_ms で上書きする
+    pub lkh_config: LKHConfig,
+}
+
+// 分割できない規模の問題でも全コアを使うため、全体に対する LKH を複数並列に走らせる
+// 各探索は kick や探索順の乱択で別々の解に進み、一定間隔で最良解を共有する
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    solution: ArraySolution,
+    config: ParallelLKHConfig,
+) -> ArraySolution {
+    let start = Instant::now();
+
+    let lkh_config = config.lkh_config;
+    let neighbor_table = NeighborTable::load_or_build(
+        distance,
+        5,
+        lkh_config.use_neighbor_cache,
+        &lkh_config.cache_filepath,
+    );
+
+    let mut best_eval = evaluate(distance, &solution);
+    let mut best_solution = solution;
+
+    // 各探索の kick の段階や dlb はラウンドをまたいで引き継ぐ
+    let mut state_list = (0..config.no_search.max(1))
+        .map(|_| LKHState::new(distance, best_solution.clone(), &lkh_config))
+        .collect::<Vec<_>>();
+
+    for round in 0.. {
+        let elapsed = start.elapsed().as_millis();
+        if elapsed >= config.time_ms {
+            break;
+        }
+        let mut lkh_config = lkh_config.clone();
+        lkh_config.time_ms = config.sync_interval_ms.min(config.time_ms - elapsed);
+
+        state_list
+            .par_iter_mut()
+            .for_each(|state| lkh::run(distance, &neighbor_table, state, &lkh_config));
+
+        let round_best = state_list
+            .iter()
+            .min_by_key(|state| state.best_eval())
+            .unwrap();
+        if round_best.best_eval() < best_eval {
+            best_eval = round_best.best_eval();
+            best_solution = round_best.best_solution().clone();
+        }
+        // 最良解より悪い探索だけをそこへ乗り換えさせる
+        for state in state_list.iter_mut() {
+            if state.best_eval() > best_eval {
+                state.adopt(&best_solution, best_eval);
+            }
+        }
+        if config.debug {
+            eprintln!("round {}: best eval = {}", round, best_eval);
+        }
+    }
+    best_solution
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        activation_order::ActivationOrder,
+        array_solution::ArraySolution,
+        evaluate::{evaluate, validate},
+        improvement_strategy::ImprovementStrategy,
+        lkh::LKHConfig,
+        test_util::random_euclid_distance,
+    };
+
+    use super::{solve, ParallelLKHConfig};
+
+    #[test]
+    fn test_parallel_lkh() {
+        const SIZE: usize = 200;
+
+        let distance = random_euclid_distance(SIZE);
+
+        let initial = ArraySolution::new(SIZE);
+        let config = ParallelLKHConfig {
+            no_search: 2,
+            time_ms: 600,
+            sync_interval_ms: 200,
+            debug: false,
+            lkh_config: LKHConfig {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: 0,
+                start_kick_step: 5,
+                kick_step_diff: 5,
+                end_kick_step: 20,
+                fail_count_threashold: 10,
+                max_depth: 5,
+                use_non_sequential_move: true,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+            },
+        };
+        let solution = solve(&distance, initial.clone(), config);
+
+        assert!(validate(&distance, &solution));
+        assert!(evaluate(&distance, &solution) < evaluate(&distance, &initial));
+    }
+}
@@ -0,0 +1,328 @@
+use std::{path::PathBuf, time::Instant};
+
+use crate::{
+    activation_order::{ActivationOrder, ActiveSet},
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    edge_constraint::is_allowed_change,
+    improvement_strategy::{ImprovementSelector, ImprovementStrategy},
+    neighbor_table::NeighborTable,
+    solution::Solution,
+    stop_reason::{StopReason, TIME_CHECK_INTERVAL},
+    two_level_tree_solution::TwoLeveltreeSolution,
+};
+
+// 巡回路上の位置に依存する目的関数
+// 頂点 0 から next の向きに辿ったとき、step 番目 (0 始まり) に通る辺 from -> to のコストを cost で返す
+// DistanceFunction::distance は位置に依存しない基準の距離で、近傍リストの作成に使う
+pub trait PositionDependentObjective: DistanceFunction {
+    fn cost(&self, step: usize, from: u32, to: u32) -> i64;
+}
+
+// period 歩ごとに、出発する頂点が免除されていなければ辺のコストを penalty_percent % 増やす
+// Kaggle Santa 2018 の「10 歩ごとに素数の都市から出発しないと 10% 長くなる」はこの形
+pub struct PenalizedStepDistance<'a, T: DistanceFunction> {
+    ref_distance: &'a T,
+    period: usize,
+    penalty_percent: i64,
+    exempt_list: Vec<bool>,
+}
+
+impl<'a, T: DistanceFunction> PenalizedStepDistance<'a, T> {
+    pub fn new(
+        ref_distance: &'a T,
+        period: usize,
+        penalty_percent: i64,
+        exempt_list: Vec<bool>,
+    ) -> PenalizedStepDistance<'a, T> {
+        assert!(period > 0);
+        assert_eq!(exempt_list.len(), ref_distance.dimension() as usize);
+        PenalizedStepDistance {
+            ref_distance,
+            period,
+            penalty_percent,
+            exempt_list,
+        }
+    }
+
+    // 頂点番号が素数なら免除する、Kaggle Santa 2018 と同じ設定
+    pub fn santa(ref_distance: &'a T) -> PenalizedStepDistance<'a, T> {
+        let n = ref_distance.dimension() as usize;
+        let mut is_prime = vec![true; n];
+        is_prime.iter_mut().take(2).for_each(|v| *v = false);
+        for id in 2..n {
+            if is_prime[id] {
+                for multiple in (id * id..n).step_by(id) {
+                    is_prime[multiple] = false;
+                }
+            }
+        }
+        PenalizedStepDistance::new(ref_distance, 10, 10, is_prime)
+    }
+}
+
+impl<'a, T: DistanceFunction> DistanceFunction for PenalizedStepDistance<'a, T> {
+    fn distance(&self, id1: u32, id2: u32) -> i64 {
+        self.ref_distance.distance(id1, id2)
+    }
+
+    fn dimension(&self) -> u32 {
+        self.ref_distance.dimension()
+    }
+
+    fn name(&self) -> String {
+        self.ref_distance.name()
+    }
+
+    fn is_symmetric(&self) -> bool {
+        self.ref_distance.is_symmetric()
+    }
+
+    fn is_fixed(&self, id1: u32, id2: u32) -> bool {
+        self.ref_distance.is_fixed(id1, id2)
+    }
+
+    fn is_forbidden(&self, id1: u32, id2: u32) -> bool {
+        self.ref_distance.is_forbidden(id1, id2)
+    }
+
+    fn fixed_edge_list(&self) -> Vec<(u32, u32)> {
+        self.ref_distance.fixed_edge_list()
+    }
+
+    fn coordinate(&self, id: u32) -> Option<(i64, i64)> {
+        self.ref_distance.coordinate(id)
+    }
+}
+
+impl<'a, T: DistanceFunction> PositionDependentObjective for PenalizedStepDistance<'a, T> {
+    fn cost(&self, step: usize, from: u32, to: u32) -> i64 {
+        let distance = self.ref_distance.distance(from, to);
+        if (step + 1).is_multiple_of(self.period) && !self.exempt_list[from as usize] {
+            distance + distance * self.penalty_percent / 100
+        } else {
+            distance
+        }
+    }
+}
+
+// 頂点 0 から next の向きに辿ったときの目的関数の値
+pub fn evaluate(objective: &impl PositionDependentObjective, solution: &impl Solution) -> i64 {
+    let mut sum = 0;
+    let mut id = 0;
+    for step in 0..objective.dimension() as usize {
+        let next = solution.next(id);
+        sum += objective.cost(step, id, next);
+        id = next;
+    }
+    sum
+}
+
+// 頂点 0 を位置 0 としたときの id の位置
+fn position_of(solution: &impl Solution, id: u32) -> usize {
+    let n = solution.len();
+    (solution.index_of(id) + n - solution.index_of(0)) % n
+}
+
+// 位置 i の辺 (a, b) と位置 j の辺 (c, d) (i < j) を (a, c), (b, d) に張り替え、
+// b から c までを反転したときのコストの減少量
+// 反転した区間の辺は向きと位置の両方が変わるので、区間の長さに比例する時間がかかる
+#[allow(clippy::too_many_arguments)]
+fn reversal_gain(
+    objective: &impl PositionDependentObjective,
+    solution: &impl Solution,
+    i: usize,
+    a: u32,
+    b: u32,
+    j: usize,
+    c: u32,
+    d: u32,
+) -> i64 {
+    let mut gain = objective.cost(i, a, b) + objective.cost(j, c, d)
+        - objective.cost(i, a, c)
+        - objective.cost(j, b, d);
+    let mut from = b;
+    for step in i + 1..j {
+        let to = solution.next(from);
+        gain += objective.cost(step, from, to) - objective.cost(i + j - step, to, from);
+        from = to;
+    }
+    gain
+}
+
+pub struct PositionOpt2Config {
+    pub use_neighbor_cache: bool,
+    pub cache_filepath: PathBuf,
+    pub debug: bool,
+    // None なら制限なし
+    pub time_ms: Option<u128>,
+    pub max_iteration: Option<usize>,
+    // 反転する区間の最大長。ゲインの計算に区間長に比例する時間がかかるので制限する
+    pub max_segment_len: usize,
+    pub improvement_strategy: ImprovementStrategy,
+    pub activation_order: ActivationOrder,
+}
+
+// 位置に依存する目的関数の下での 2-opt
+// 頂点 0 を含まない側の区間を反転することで、頂点 0 からの向きを保ったまま位置を index_of で求める
+pub fn solve(
+    objective: &(impl PositionDependentObjective + std::marker::Sync),
+    solution: ArraySolution,
+    config: PositionOpt2Config,
+) -> (ArraySolution, StopReason) {
+    let neighbor_table = NeighborTable::load_or_build(
+        objective,
+        5,
+        config.use_neighbor_cache,
+        &config.cache_filepath,
+    );
+
+    let n = solution.len();
+
+    let start = Instant::now();
+
+    let mut tlt = TwoLeveltreeSolution::<1000>::new(&solution);
+
+    let mut rng = rand::thread_rng();
+
+    let mut dlb = ActiveSet::new(n, config.activation_order);
+    dlb.set_all();
+
+    let mut eval = evaluate(objective, &tlt);
+
+    let mut stop_reason = StopReason::LocalOptimum;
+
+    for iter in 0.. {
+        let a = dlb.select(&mut rng);
+
+        let mut selector = ImprovementSelector::new(config.improvement_strategy);
+
+        'search: for &c in neighbor_table.neighbor_list(a) {
+            // 新しい辺 (a, c) を張る 2 通りの張り替え
+            for (p, q) in [(a, c), (tlt.prev(a), tlt.prev(c))] {
+                let (mut p, mut q) = (p, q);
+                let (mut i, mut j) = (position_of(&tlt, p), position_of(&tlt, q));
+                if i > j {
+                    (p, q) = (q, p);
+                    (i, j) = (j, i);
+                }
+                if j - i < 2 || j - i > config.max_segment_len {
+                    continue;
+                }
+                let (r, s) = (tlt.next(p), tlt.next(q));
+                if !is_allowed_change(objective, &[(p, r), (q, s)], &[(p, q), (r, s)]) {
+                    continue;
+                }
+                let gain = reversal_gain(objective, &tlt, i, p, r, j, q, s);
+                if selector.update(gain, (p, r, q, s)) {
+                    break 'search;
+                }
+            }
+        }
+        if let Some((best_gain, (p, r, q, s))) = selector.into_best() {
+            for id in [p, r, q, s] {
+                dlb.push(id);
+            }
+            tlt.swap(r, q);
+            eval -= best_gain;
+            debug_assert_eq!(eval, evaluate(objective, &tlt));
+        } else {
+            dlb.remove(a);
+        }
+
+        if config.debug && (iter % n == 0 || dlb.is_empty()) {
+            eprintln!("iter = {}, eval = {}", iter, eval);
+            eprintln!("dlb size = {}", dlb.len());
+        }
+        if dlb.is_empty() {
+            break;
+        }
+        if config
+            .max_iteration
+            .is_some_and(|max_iteration| iter + 1 >= max_iteration)
+        {
+            stop_reason = StopReason::IterationLimit;
+            break;
+        }
+        if iter % TIME_CHECK_INTERVAL == 0
+            && config
+                .time_ms
+                .is_some_and(|time_ms| start.elapsed().as_millis() > time_ms)
+        {
+            stop_reason = StopReason::TimeLimit;
+            break;
+        }
+    }
+    (tlt.to_array_solution(), stop_reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::{seq::SliceRandom, thread_rng, Rng};
+
+    use crate::{
+        activation_order::ActivationOrder, array_solution::ArraySolution,
+        improvement_strategy::ImprovementStrategy, solution::Solution,
+        test_util::random_euclid_distance, two_level_tree_solution::TwoLeveltreeSolution,
+    };
+
+    use super::{
+        evaluate, position_of, reversal_gain, solve, PenalizedStepDistance, PositionOpt2Config,
+    };
+
+    #[test]
+    fn test_reversal_gain() {
+        const SIZE: usize = 50;
+        let mut rng = thread_rng();
+
+        let distance = random_euclid_distance(SIZE);
+        let objective = PenalizedStepDistance::santa(&distance);
+
+        let mut vertex_array = (0..SIZE as u32).collect::<Vec<_>>();
+        vertex_array[1..].shuffle(&mut rng);
+        let mut tlt = TwoLeveltreeSolution::<1000>::new(&ArraySolution::from_array(vertex_array));
+
+        for _iter in 0..100 {
+            let i = rng.gen_range(0..SIZE - 2);
+            let j = rng.gen_range(i + 2..SIZE);
+            let a = tlt.id_of((tlt.index_of(0) + i) % SIZE);
+            let c = tlt.id_of((tlt.index_of(0) + j) % SIZE);
+            assert_eq!(position_of(&tlt, a), i);
+            assert_eq!(position_of(&tlt, c), j);
+            let (b, d) = (tlt.next(a), tlt.next(c));
+
+            let before = evaluate(&objective, &tlt);
+            let gain = reversal_gain(&objective, &tlt, i, a, b, j, c, d);
+            tlt.swap(b, c);
+            assert_eq!(before - gain, evaluate(&objective, &tlt));
+        }
+    }
+
+    #[test]
+    fn test_solve() {
+        const SIZE: usize = 500;
+
+        let distance = random_euclid_distance(SIZE);
+        let objective = PenalizedStepDistance::santa(&distance);
+
+        let initial = ArraySolution::new(SIZE);
+        let initial_eval = evaluate(&objective, &initial);
+        let (solution, _) = solve(
+            &objective,
+            initial,
+            PositionOpt2Config {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: None,
+                max_iteration: None,
+                max_segment_len: SIZE,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+            },
+        );
+        assert!(evaluate(&objective, &solution) < initial_eval / 2);
+    }
+}
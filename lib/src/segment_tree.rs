@@ -56,7 +56,7 @@ where
         }
     }
 
-    pub fn to_swap_list(self) -> Vec<(u32, u32)> {
+    pub fn into_swap_list(self) -> Vec<(u32, u32)> {
         self.swap_id_list
     }
 }
@@ -203,8 +203,13 @@ mod tests {
             solution.swap(from, to);
             segment_tree.swap(from, to);
 
+            // TwoLevelTree は swap で物理的な先頭位置がずれうるので、回転を許して比較する
+            let offset = solution.index_of(segment_tree.id_of(0));
             for index in 0..SIZE {
-                assert_eq!(solution.id_of(index), segment_tree.id_of(index));
+                assert_eq!(
+                    solution.id_of((offset + index) % SIZE),
+                    segment_tree.id_of(index)
+                );
             }
 
             for id in 0..SIZE as u32 {
@@ -217,8 +222,13 @@ mod tests {
             solution.swap(to, from);
             segment_tree.undo();
 
+            // TwoLevelTree は swap で物理的な先頭位置がずれうるので、回転を許して比較する
+            let offset = solution.index_of(segment_tree.id_of(0));
             for index in 0..SIZE {
-                assert_eq!(solution.id_of(index), segment_tree.id_of(index));
+                assert_eq!(
+                    solution.id_of((offset + index) % SIZE),
+                    segment_tree.id_of(index)
+                );
             }
 
             for id in 0..SIZE as u32 {
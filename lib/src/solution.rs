@@ -11,6 +11,10 @@ pub trait Solution {
 
     fn len(&self) -> usize;
 
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn print(&self) {
         let mut id = 0;
         eprint!("[");
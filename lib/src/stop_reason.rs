@@ -0,0 +1,13 @@
+// 局所探索がなぜ終了したか
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    // 改善する近傍がなくなった
+    LocalOptimum,
+    // 制限時間に達した
+    TimeLimit,
+    // 制限 iteration 数に達した
+    IterationLimit,
+}
+
+// 局所探索で制限時間を確認する iteration の間隔
+pub(crate) const TIME_CHECK_INTERVAL: usize = 100;
@@ -0,0 +1,155 @@
+use crate::{
+    array_solution::ArraySolution,
+    distance::DistanceFunction,
+    edge_constraint::{FIXED_EDGE_COST, FORBIDDEN_EDGE_COST},
+    solution::Solution,
+};
+
+// 非対称な n 頂点の問題を、対称な 2n 頂点の問題に変換する (Jonker-Volgenant の変換)
+// 頂点 i の複製を i + n とし、i + n と j の間の辺のコストを i から j への距離とする
+// 対称な問題の巡回路は i -> i + n -> j -> j + n -> ... の形になり、元の問題の i -> j -> ... に対応する
+// 頂点 i と複製 i + n を繋ぐ辺は固定辺とする
+// 元の頂点同士と複製同士を繋ぐ辺はコストだけで避け、禁止辺としては報告しない
+// 2-opt の kick はこの辺を一時的に張らないと打てず、局所探索がすぐに取り除くため
+pub struct SymmetricTransform<'a, T: DistanceFunction> {
+    ref_distance: &'a T,
+    name: String,
+}
+
+impl<'a, T: DistanceFunction> SymmetricTransform<'a, T> {
+    pub fn new(ref_distance: &'a T) -> SymmetricTransform<'a, T> {
+        SymmetricTransform {
+            ref_distance,
+            name: format!("{}.sym", ref_distance.name()),
+        }
+    }
+
+    // 対称な問題の巡回路を、元の問題の巡回路に戻す
+    pub fn to_asymmetric_solution(&self, solution: &impl Solution) -> ArraySolution {
+        let n = self.ref_distance.dimension();
+        // 0 の次が複製 n になる向きに辿る
+        let forward = solution.next(0) == n;
+        let mut vertex_array = Vec::with_capacity(n as usize);
+        let mut id = 0;
+        for _iter in 0..2 * n {
+            if id < n {
+                vertex_array.push(id);
+            }
+            id = if forward {
+                solution.next(id)
+            } else {
+                solution.prev(id)
+            };
+        }
+        ArraySolution::from_array(vertex_array)
+    }
+
+    // 元の問題の巡回路を、対称な問題の巡回路にする
+    pub fn to_symmetric_solution(&self, solution: &impl Solution) -> ArraySolution {
+        let n = self.ref_distance.dimension();
+        let mut vertex_array = Vec::with_capacity(2 * n as usize);
+        let mut id = 0;
+        for _iter in 0..n {
+            vertex_array.push(id);
+            vertex_array.push(id + n);
+            id = solution.next(id);
+        }
+        ArraySolution::from_array(vertex_array)
+    }
+}
+
+impl<'a, T: DistanceFunction> DistanceFunction for SymmetricTransform<'a, T> {
+    fn distance(&self, id1: u32, id2: u32) -> i64 {
+        let n = self.ref_distance.dimension();
+        let (id1, id2) = (id1.min(id2), id1.max(id2));
+        if id1 == id2 {
+            0
+        } else if id2 < n || id1 >= n {
+            FORBIDDEN_EDGE_COST
+        } else if self.is_fixed(id1, id2) {
+            FIXED_EDGE_COST
+        } else {
+            // id2 の複製から id1 へ向かう辺
+            self.ref_distance.distance(id2 - n, id1)
+        }
+    }
+
+    fn dimension(&self) -> u32 {
+        2 * self.ref_distance.dimension()
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn is_fixed(&self, id1: u32, id2: u32) -> bool {
+        id1.abs_diff(id2) == self.ref_distance.dimension()
+    }
+
+    fn fixed_edge_list(&self) -> Vec<(u32, u32)> {
+        let n = self.ref_distance.dimension();
+        (0..n).map(|id| (id, id + n)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::{thread_rng, Rng};
+
+    use crate::{
+        activation_order::ActivationOrder,
+        array_solution::ArraySolution,
+        distance::DistanceFunction,
+        evaluate::{evaluate, validate},
+        exact,
+        improvement_strategy::ImprovementStrategy,
+        lkh::{self, LKHConfig},
+        matrix_distance::MatrixDistance,
+    };
+
+    use super::SymmetricTransform;
+
+    #[test]
+    fn test_symmetric_transform() {
+        const SIZE: usize = 12;
+        let mut rng = thread_rng();
+        let matrix = (0..SIZE)
+            .map(|_| (0..SIZE).map(|_| rng.gen_range(1..100)).collect())
+            .collect();
+        let distance = MatrixDistance::new(matrix, "random".to_string());
+        assert!(!distance.is_symmetric());
+
+        let transform = SymmetricTransform::new(&distance);
+        assert!(transform.is_symmetric());
+        let initial = transform.to_symmetric_solution(&ArraySolution::new(SIZE));
+        assert!(validate(&transform, &initial));
+        let solution = lkh::solve(
+            &transform,
+            initial,
+            LKHConfig {
+                use_neighbor_cache: false,
+                cache_filepath: PathBuf::new(),
+                debug: false,
+                time_ms: 1_000,
+                start_kick_step: 5,
+                kick_step_diff: 5,
+                end_kick_step: 10,
+                fail_count_threashold: 10,
+                max_depth: 5,
+                use_non_sequential_move: true,
+                improvement_strategy: ImprovementStrategy::BestImprovement,
+                activation_order: ActivationOrder::Random,
+            },
+        );
+        assert!(validate(&transform, &solution));
+        let solution = transform.to_asymmetric_solution(&solution);
+
+        let (optimal, _) = exact::solve_dp(&distance);
+        assert_eq!(
+            evaluate(&distance, &solution),
+            evaluate(&distance, &optimal)
+        );
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use rand::{thread_rng, Rng};
+
+use crate::euclid_distance::EuclidDistance;
+
+// [0, 1000) の正方形に一様に頂点を置いた問題
+pub(crate) fn random_euclid_distance(size: usize) -> EuclidDistance {
+    random_euclid_distance_with(size, &mut thread_rng())
+}
+
+// シードを固定した乱数で、同じ問題を作り直したいとき用
+pub(crate) fn random_euclid_distance_with(size: usize, rng: &mut impl Rng) -> EuclidDistance {
+    let coordinate_list = (0..size)
+        .map(|_| (rng.gen_range(0..1000), rng.gen_range(0..1000)))
+        .collect::<Vec<_>>();
+    EuclidDistance::new(&coordinate_list, "random".to_string())
+}
+
+// 一時ファイルのパス。同時に走る別の cargo test とぶつからないよう、プロセス id を付ける
+pub(crate) fn temp_filepath(filename: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{}_{}", std::process::id(), filename))
+}
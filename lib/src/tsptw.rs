@@ -0,0 +1,640 @@
+use std::time::Instant;
+
+use crate::{
+    array_solution::ArraySolution, distance::DistanceFunction, neighbor_table::NeighborTable,
+    solution::Solution,
+};
+
+// 頂点の時間枠。ready より早く着いたら待ち、due より遅く着いたら違反とする
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub ready: i64,
+    pub due: i64,
+    // 作業時間
+    pub service: i64,
+}
+
+// 時間枠付き巡回セールスマン問題の、距離以外の情報
+// 距離をそのまま移動時間とし、デポを時刻 ready に出発して全頂点を回り、デポに戻る
+pub struct Tsptw {
+    pub depot: u32,
+    pub window_list: Vec<TimeWindow>,
+}
+
+impl Tsptw {
+    pub fn new(depot: u32, window_list: Vec<TimeWindow>) -> Tsptw {
+        assert!(window_list
+            .iter()
+            .all(|window| window.ready <= window.due && window.service >= 0));
+        Tsptw { depot, window_list }
+    }
+
+    // due の早い順に回る初期解
+    pub fn initial_solution(&self) -> ArraySolution {
+        let mut vertex_array = (0..self.window_list.len() as u32)
+            .filter(|&id| id != self.depot)
+            .collect::<Vec<_>>();
+        vertex_array.sort_by_key(|&id| {
+            let window = &self.window_list[id as usize];
+            (window.due, window.ready)
+        });
+        vertex_array.insert(0, self.depot);
+        ArraySolution::from_array(vertex_array)
+    }
+}
+
+// 目的関数の値。時間枠の違反の合計を優先し、次に長さを比べる
+pub type TsptwEval = (i64, i64);
+
+// デポから next の向きに辿ったときの (違反の合計, 長さ)
+pub fn evaluate(
+    distance: &impl DistanceFunction,
+    tsptw: &Tsptw,
+    solution: &impl Solution,
+) -> TsptwEval {
+    Schedule::new(distance, tsptw, solution).eval()
+}
+
+// 区間の最小値を O(1) で答える sparse table
+struct SparseTable {
+    table: Vec<Vec<i64>>,
+}
+
+impl SparseTable {
+    fn new(value_list: Vec<i64>) -> SparseTable {
+        let mut table = vec![value_list];
+        let mut width = 1;
+        while 2 * width <= table[0].len() {
+            let last = table.last().unwrap();
+            let next = (0..last.len() - width)
+                .map(|index| last[index].min(last[index + width]))
+                .collect();
+            table.push(next);
+            width *= 2;
+        }
+        SparseTable { table }
+    }
+
+    // 位置 from から to までの最小値
+    fn min(&self, from: usize, to: usize) -> i64 {
+        let level = (to - from + 1).ilog2() as usize;
+        self.table[level][from].min(self.table[level][to + 1 - (1 << level)])
+    }
+}
+
+// id_list を順に訪れたときの (違反の合計, 長さ, 最後の頂点, 最後の頂点を出る時刻)
+// last を時刻 time に出発する
+fn visit(
+    distance: &impl DistanceFunction,
+    tsptw: &Tsptw,
+    last: u32,
+    time: i64,
+    id_list: &[u32],
+) -> (i64, i64, u32, i64) {
+    let (mut violation, mut length, mut last, mut time) = (0, 0, last, time);
+    for &id in id_list {
+        let window = &tsptw.window_list[id as usize];
+        let d = distance.distance(last, id);
+        length += d;
+        let start = (time + d).max(window.ready);
+        violation += (start - window.due).max(0);
+        time = start + window.service;
+        last = id;
+    }
+    (violation, length, last, time)
+}
+
+// 巡回路の各位置での時刻と、移動の評価に使う累積値
+// 位置 0 はデポの出発、位置 n はデポへの帰着
+struct Schedule {
+    sequence: Vec<u32>,
+    position: Vec<usize>,
+    arrival: Vec<i64>,
+    start: Vec<i64>,
+    // その位置までの違反の合計
+    violation_prefix: Vec<i64>,
+    // その位置までの長さ
+    length_prefix: Vec<i64>,
+    // その位置までの待ち時間の合計
+    wait_prefix: Vec<i64>,
+    // forward time slack。その位置への到着がこれ以下だけ遅れても、以降の違反は増えない
+    slack: Vec<i64>,
+    // 区間 [a, b] の forward time slack は min(wait_prefix[k] + due[k] - start[k]) - wait_prefix[a - 1]
+    slack_table: SparseTable,
+    // start[k] - ready[k]。区間を早めたとき、各位置の開始はこれ以上は早まらない
+    advance_table: SparseTable,
+}
+
+impl Schedule {
+    fn new(distance: &impl DistanceFunction, tsptw: &Tsptw, solution: &impl Solution) -> Schedule {
+        let n = solution.len();
+        let mut sequence = Vec::with_capacity(n + 1);
+        let mut id = tsptw.depot;
+        for _iter in 0..n {
+            sequence.push(id);
+            id = solution.next(id);
+        }
+        sequence.push(tsptw.depot);
+
+        let mut position = vec![0; n];
+        for (index, &id) in sequence.iter().take(n).enumerate() {
+            position[id as usize] = index;
+        }
+
+        let depot_window = &tsptw.window_list[tsptw.depot as usize];
+        let mut arrival = vec![depot_window.ready; n + 1];
+        let mut start = vec![depot_window.ready; n + 1];
+        let mut violation_prefix = vec![0; n + 1];
+        let mut length_prefix = vec![0; n + 1];
+        let mut wait_prefix = vec![0; n + 1];
+        for index in 1..=n {
+            let (prev, id) = (sequence[index - 1], sequence[index]);
+            let window = &tsptw.window_list[id as usize];
+            let d = distance.distance(prev, id);
+            arrival[index] = start[index - 1] + tsptw.window_list[prev as usize].service + d;
+            start[index] = arrival[index].max(window.ready);
+            violation_prefix[index] =
+                violation_prefix[index - 1] + (start[index] - window.due).max(0);
+            length_prefix[index] = length_prefix[index - 1] + d;
+            wait_prefix[index] = wait_prefix[index - 1] + start[index] - arrival[index];
+        }
+
+        // 到着が δ 遅れると、位置 k の開始は待ち時間に吸収された残りだけ遅れる
+        // slack[i] = wait[i] + min(due[i] - start[i], slack[i + 1])
+        let mut slack = vec![i64::MAX; n + 2];
+        for index in (1..=n).rev() {
+            let window = &tsptw.window_list[sequence[index] as usize];
+            let wait = start[index] - arrival[index];
+            slack[index] = (window.due - start[index])
+                .min(slack[index + 1])
+                .saturating_add(wait);
+        }
+
+        let slack_table = SparseTable::new(
+            (0..=n)
+                .map(|index| {
+                    let window = &tsptw.window_list[sequence[index] as usize];
+                    wait_prefix[index] + window.due - start[index]
+                })
+                .collect(),
+        );
+        let advance_table = SparseTable::new(
+            (0..=n)
+                .map(|index| start[index] - tsptw.window_list[sequence[index] as usize].ready)
+                .collect(),
+        );
+
+        Schedule {
+            sequence,
+            position,
+            arrival,
+            start,
+            violation_prefix,
+            length_prefix,
+            wait_prefix,
+            slack,
+            slack_table,
+            advance_table,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sequence.len() - 1
+    }
+
+    fn eval(&self) -> TsptwEval {
+        let n = self.len();
+        (self.violation_prefix[n], self.length_prefix[n])
+    }
+
+    // 位置 index の頂点を出る時刻
+    fn leave(&self, tsptw: &Tsptw, index: usize) -> i64 {
+        self.start[index] + tsptw.window_list[self.sequence[index] as usize].service
+    }
+
+    // 位置 j に時刻 arrival に着いたときの、位置 j 以降の違反の合計
+    // 到着の遅れが slack 以下なら違反は変わらず、そうでなければ開始時刻が元と一致するまで辿る
+    fn tail_violation(
+        &self,
+        distance: &impl DistanceFunction,
+        tsptw: &Tsptw,
+        j: usize,
+        arrival: i64,
+    ) -> i64 {
+        let n = self.len();
+        let delay = arrival - self.arrival[j];
+        if delay == 0 || (delay > 0 && delay <= self.slack[j]) {
+            return self.violation_prefix[n] - self.violation_prefix[j - 1];
+        }
+
+        let mut violation = 0;
+        let mut arrival = arrival;
+        for index in j..=n {
+            let id = self.sequence[index];
+            let window = &tsptw.window_list[id as usize];
+            let start = arrival.max(window.ready);
+            if start == self.start[index] {
+                // ここから先の時刻は元の巡回路と同じ
+                violation += self.violation_prefix[n] - self.violation_prefix[index - 1];
+                break;
+            }
+            violation += (start - window.due).max(0);
+            if index < n {
+                let next = self.sequence[index + 1];
+                arrival = start + window.service + distance.distance(id, next);
+            }
+        }
+        violation
+    }
+
+    // 違反の無い巡回路か
+    fn is_feasible(&self) -> bool {
+        self.violation_prefix[self.len()] == 0
+    }
+
+    // 位置 a から b までの区間に、元と異なる時刻 arrival に着いたときの (区間の違反の合計, 位置 b を出る時刻)
+    // 遅れが区間の slack 以下か、違反の無い区間が早まる場合は、区間の累積値から O(1) で求める
+    // 元の巡回路に違反が無ければ、slack を超える遅れは違反を増やすだけなので None を返す
+    // それ以外は、違反の減り方や増え方が頂点ごとに違うので区間を辿る
+    fn shift_block(
+        &self,
+        distance: &impl DistanceFunction,
+        tsptw: &Tsptw,
+        a: usize,
+        b: usize,
+        arrival: i64,
+    ) -> Option<(i64, i64)> {
+        let delay = arrival - self.arrival[a];
+        let violation = self.violation_prefix[b] - self.violation_prefix[a - 1];
+        let leave = self.leave(tsptw, b);
+        if delay >= 0 {
+            let slack = self.slack_table.min(a, b) - self.wait_prefix[a - 1];
+            if delay == 0 || delay <= slack {
+                // 遅れは待ち時間に吸収された残りだけ伝わる
+                let wait = self.wait_prefix[b] - self.wait_prefix[a - 1];
+                return Some((violation, leave + (delay - wait).max(0)));
+            }
+            if self.is_feasible() {
+                return None;
+            }
+        } else if violation == 0 {
+            // 各位置の開始は ready より前には早まらない
+            return Some((0, leave + delay.max(-self.advance_table.min(a, b))));
+        }
+
+        let mut violation = 0;
+        let mut time = arrival;
+        for index in a..=b {
+            let id = self.sequence[index];
+            let window = &tsptw.window_list[id as usize];
+            let start = time.max(window.ready);
+            violation += (start - window.due).max(0);
+            time = start + window.service;
+            if index < b {
+                time += distance.distance(id, self.sequence[index + 1]);
+            }
+        }
+        Some((violation, time))
+    }
+
+    // 位置 i までをそのまま通り、middle を順に訪れ、位置 j 以降をそのまま通る巡回路の評価値
+    fn evaluate_move(
+        &self,
+        distance: &impl DistanceFunction,
+        tsptw: &Tsptw,
+        i: usize,
+        middle: &[u32],
+        j: usize,
+    ) -> TsptwEval {
+        let n = self.len();
+        let (violation, length, last, time) = visit(
+            distance,
+            tsptw,
+            self.sequence[i],
+            self.leave(tsptw, i),
+            middle,
+        );
+        let d = distance.distance(last, self.sequence[j]);
+        (
+            self.violation_prefix[i]
+                + violation
+                + self.tail_violation(distance, tsptw, j, time + d),
+            self.length_prefix[i] + length + d + self.length_prefix[n] - self.length_prefix[j],
+        )
+    }
+
+    // 位置 s から e までの部分列を、位置 p と p + 1 の間へ移した巡回路の評価値
+    // 部分列は短いので辿り、間の区間は shift_block で O(1) で評価する
+    // 元の巡回路に違反が無いとき、間の区間やその後ろへの到着の遅れが slack を超える移動は、違反が増えるので調べずに None を返す
+    fn evaluate_or_opt(
+        &self,
+        distance: &impl DistanceFunction,
+        tsptw: &Tsptw,
+        s: usize,
+        e: usize,
+        p: usize,
+    ) -> Option<TsptwEval> {
+        let n = self.len();
+        let sequence = &self.sequence;
+        let segment = &sequence[s..=e];
+        let (mut violation, mut length, last, time, j) = if p < s {
+            // [.., p], [s, e], [p + 1, s - 1], [e + 1, ..]
+            let (violation, length, last, time) =
+                visit(distance, tsptw, sequence[p], self.leave(tsptw, p), segment);
+            let d = distance.distance(last, sequence[p + 1]);
+            let (block_violation, time) =
+                self.shift_block(distance, tsptw, p + 1, s - 1, time + d)?;
+            (
+                self.violation_prefix[p] + violation + block_violation,
+                self.length_prefix[p] + length + d + self.length_prefix[s - 1]
+                    - self.length_prefix[p + 1],
+                sequence[s - 1],
+                time,
+                e + 1,
+            )
+        } else {
+            // [.., s - 1], [e + 1, p], [s, e], [p + 1, ..]
+            let d = distance.distance(sequence[s - 1], sequence[e + 1]);
+            let (block_violation, time) =
+                self.shift_block(distance, tsptw, e + 1, p, self.leave(tsptw, s - 1) + d)?;
+            let (violation, length, last, time) =
+                visit(distance, tsptw, sequence[p], time, segment);
+            (
+                self.violation_prefix[s - 1] + block_violation + violation,
+                self.length_prefix[s - 1] + d + self.length_prefix[p] - self.length_prefix[e + 1]
+                    + length,
+                last,
+                time,
+                p + 1,
+            )
+        };
+
+        let d = distance.distance(last, sequence[j]);
+        let arrival = time + d;
+        let delay = arrival - self.arrival[j];
+        if delay > 0 && delay > self.slack[j] && self.is_feasible() {
+            return None;
+        }
+        violation += self.tail_violation(distance, tsptw, j, arrival);
+        length += d + self.length_prefix[n] - self.length_prefix[j];
+        Some((violation, length))
+    }
+}
+
+pub struct TsptwConfig {
+    pub debug: bool,
+    // None なら局所最適になるまで
+    pub time_ms: Option<u128>,
+    // 候補とする近傍の数
+    pub neighbor_size: usize,
+    // or-opt で動かす部分列の最大長
+    pub or_opt_max_len: usize,
+}
+
+// 違反の合計、長さの順に辞書式で最も良くなる移動
+enum TsptwMove {
+    // 位置 i + 1 から j までを反転する
+    TwoOpt(usize, usize),
+    // 位置 s から e までの部分列を、位置 p と p + 1 の間へ移す
+    OrOpt(usize, usize, usize),
+}
+
+// 時間枠を考慮した 2-opt と or-opt
+// 移動の評価は Schedule の累積値と forward time slack を使い、変化した区間とその後の時刻が元に戻るまでだけを辿る
+// or-opt は間の区間を累積値で評価し、違反の無い巡回路では slack を超えて遅らせる移動は調べない
+pub fn solve(
+    distance: &(impl DistanceFunction + std::marker::Sync),
+    tsptw: &Tsptw,
+    solution: ArraySolution,
+    config: TsptwConfig,
+) -> ArraySolution {
+    let start_time = Instant::now();
+    let n = solution.len();
+    assert_eq!(n, tsptw.window_list.len());
+    let neighbor_table = NeighborTable::new(distance, config.neighbor_size);
+
+    let mut solution = solution;
+    let mut middle = Vec::with_capacity(n);
+    for iter in 0.. {
+        let schedule = Schedule::new(distance, tsptw, &solution);
+        let mut best_eval = schedule.eval();
+        let mut best_move = None;
+
+        for i in 0..n {
+            let from = schedule.sequence[i];
+            for &to in neighbor_table.neighbor_list(from) {
+                // 2-opt: (from, to) を新しく張る
+                // to が後ろにあれば位置 i + 1 から j まで、前にあれば j から i - 1 までを反転する
+                let j = schedule.position[to as usize];
+                let two_opt = if j > i + 1 {
+                    Some((i, j))
+                } else if j >= 1 && j + 1 < i {
+                    Some((j - 1, i - 1))
+                } else {
+                    None
+                };
+                if let Some((a, b)) = two_opt {
+                    middle.clear();
+                    middle.extend(schedule.sequence[a + 1..=b].iter().rev());
+                    let eval = schedule.evaluate_move(distance, tsptw, a, &middle, b + 1);
+                    if eval < best_eval {
+                        best_eval = eval;
+                        best_move = Some(TsptwMove::TwoOpt(a, b));
+                    }
+                }
+
+                // or-opt: 位置 s から始まる部分列を to の直後か直前へ移す
+                let s = i;
+                if s == 0 {
+                    continue;
+                }
+                for e in s..(s + config.or_opt_max_len).min(n) {
+                    for p in [j, j.wrapping_sub(1)] {
+                        if p >= n || (s - 1..=e).contains(&p) {
+                            continue;
+                        }
+                        let Some(eval) = schedule.evaluate_or_opt(distance, tsptw, s, e, p) else {
+                            continue;
+                        };
+                        if eval < best_eval {
+                            best_eval = eval;
+                            best_move = Some(TsptwMove::OrOpt(s, e, p));
+                        }
+                    }
+                }
+            }
+        }
+
+        let Some(best_move) = best_move else {
+            break;
+        };
+        // 巡回路の向きを保つように、区間の反転だけで移動を行う
+        let sequence = &schedule.sequence;
+        match best_move {
+            TsptwMove::TwoOpt(i, j) => solution.swap(sequence[i + 1], sequence[j]),
+            TsptwMove::OrOpt(s, e, p) => {
+                // 2 つの隣り合う区間を入れ替えるのは、それぞれを反転してから全体を反転するのと同じ
+                let (first, last, mid) = if p < s { (p + 1, e, s) } else { (s, p, e + 1) };
+                solution.swap(sequence[first], sequence[mid - 1]);
+                solution.swap(sequence[mid], sequence[last]);
+                solution.swap(sequence[mid - 1], sequence[mid]);
+            }
+        }
+        debug_assert_eq!(best_eval, evaluate(distance, tsptw, &solution));
+
+        if config.debug {
+            eprintln!(
+                "iter = {}, violation = {}, length = {}",
+                iter, best_eval.0, best_eval.1
+            );
+        }
+        if config
+            .time_ms
+            .is_some_and(|time_ms| start_time.elapsed().as_millis() > time_ms)
+        {
+            break;
+        }
+    }
+    solution
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{seq::SliceRandom, thread_rng, Rng};
+
+    use crate::{
+        array_solution::ArraySolution, distance::DistanceFunction, euclid_distance::EuclidDistance,
+        solution::Solution, test_util::random_euclid_distance,
+    };
+
+    use super::{evaluate, solve, Schedule, TimeWindow, Tsptw, TsptwConfig};
+
+    // 問題と、時間枠を作るのに使った違反の無い巡回路
+    fn random_instance(size: usize) -> (EuclidDistance, Tsptw, ArraySolution) {
+        let mut rng = thread_rng();
+        let distance = random_euclid_distance(size);
+
+        // ランダムな巡回路に沿った時刻を中心に時間枠を作り、実行可能解があるようにする
+        let mut order = (1..size as u32).collect::<Vec<_>>();
+        order.shuffle(&mut rng);
+        let mut window_list = vec![
+            TimeWindow {
+                ready: 0,
+                due: 0,
+                service: 0,
+            };
+            size
+        ];
+        let mut time = 0;
+        let mut last = 0;
+        for &id in &order {
+            time += distance.distance(last, id) + 5;
+            window_list[id as usize] = TimeWindow {
+                ready: time - rng.gen_range(0..500),
+                due: time + rng.gen_range(0..500),
+                service: 5,
+            };
+            last = id;
+        }
+        window_list[0].due = time + distance.distance(last, 0) + 1000;
+        order.insert(0, 0);
+        (
+            distance,
+            Tsptw::new(0, window_list),
+            ArraySolution::from_array(order),
+        )
+    }
+
+    #[test]
+    fn test_evaluate_move() {
+        const SIZE: usize = 30;
+        let mut rng = thread_rng();
+        let (distance, tsptw, _) = random_instance(SIZE);
+
+        let mut vertex_array = (0..SIZE as u32).collect::<Vec<_>>();
+        vertex_array[1..].shuffle(&mut rng);
+        let solution = ArraySolution::from_array(vertex_array);
+        let schedule = Schedule::new(&distance, &tsptw, &solution);
+
+        for _iter in 0..100 {
+            let i = rng.gen_range(0..SIZE - 1);
+            let j = rng.gen_range(i + 1..=SIZE);
+            let mut middle = schedule.sequence[i + 1..j].to_vec();
+            middle.shuffle(&mut rng);
+
+            let mut vertex_array = schedule.sequence[..=i].to_vec();
+            vertex_array.extend(&middle);
+            vertex_array.extend(&schedule.sequence[j..SIZE]);
+            let expected = evaluate(&distance, &tsptw, &ArraySolution::from_array(vertex_array));
+            assert_eq!(
+                schedule.evaluate_move(&distance, &tsptw, i, &middle, j),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_or_opt() {
+        const SIZE: usize = 30;
+        let mut rng = thread_rng();
+        let (distance, tsptw, feasible) = random_instance(SIZE);
+
+        let mut vertex_array = (0..SIZE as u32).collect::<Vec<_>>();
+        vertex_array[1..].shuffle(&mut rng);
+        let infeasible = ArraySolution::from_array(vertex_array);
+        assert_eq!(evaluate(&distance, &tsptw, &feasible).0, 0);
+        assert!(evaluate(&distance, &tsptw, &infeasible).0 > 0);
+
+        for solution in [feasible, infeasible] {
+            let schedule = Schedule::new(&distance, &tsptw, &solution);
+            let mut no_evaluated = 0;
+            for _iter in 0..1000 {
+                let s = rng.gen_range(1..SIZE);
+                let e = rng.gen_range(s..(s + 3).min(SIZE));
+                let p = rng.gen_range(0..SIZE);
+                if (s - 1..=e).contains(&p) {
+                    continue;
+                }
+                let mut vertex_array = schedule.sequence[..SIZE].to_vec();
+                let segment = vertex_array.drain(s..=e).collect::<Vec<_>>();
+                let insert_at = if p < s { p + 1 } else { p + 1 - segment.len() };
+                vertex_array.splice(insert_at..insert_at, segment);
+                let expected =
+                    evaluate(&distance, &tsptw, &ArraySolution::from_array(vertex_array));
+
+                match schedule.evaluate_or_opt(&distance, &tsptw, s, e, p) {
+                    Some(eval) => {
+                        assert_eq!(eval, expected);
+                        no_evaluated += 1;
+                    }
+                    // 違反の無い巡回路で、違反が増える移動だけを飛ばす
+                    None => assert!(schedule.is_feasible() && expected.0 > 0),
+                }
+            }
+            assert!(no_evaluated > 0);
+        }
+    }
+
+    #[test]
+    fn test_solve() {
+        const SIZE: usize = 40;
+        let (distance, tsptw, _) = random_instance(SIZE);
+
+        let initial = tsptw.initial_solution();
+        let initial_eval = evaluate(&distance, &tsptw, &initial);
+        let solution = solve(
+            &distance,
+            &tsptw,
+            initial,
+            TsptwConfig {
+                debug: false,
+                time_ms: None,
+                neighbor_size: 10,
+                or_opt_max_len: 3,
+            },
+        );
+        let eval = evaluate(&distance, &tsptw, &solution);
+        assert!(eval <= initial_eval);
+        assert_eq!(solution.next(solution.prev(0)), 0);
+    }
+}
@@ -41,6 +41,7 @@ impl<const N: usize> Segment<N> {
         }
     }
 
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.reversed = false;
         self.index = 0;
@@ -51,11 +52,13 @@ impl<const N: usize> Segment<N> {
         self.index += 1;
     }
 
+    #[allow(dead_code)]
     pub fn pop(&mut self) {
         assert!(!self.is_empty());
         self.index -= 1;
     }
 
+    #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.index == 0
     }
@@ -91,7 +94,7 @@ struct SegmentIDList {
 }
 
 impl SegmentIDList {
-    const NONE: u16 = std::u16::MAX;
+    const NONE: u16 = u16::MAX;
     fn new(n: u16) -> SegmentIDList {
         SegmentIDList {
             content: vec![],
@@ -212,6 +215,7 @@ impl SegmentIDList {
         self.content.len()
     }
 
+    #[allow(dead_code)]
     fn validate(&self) {
         // content + free_list = capacity
         assert_eq!(
@@ -252,7 +256,7 @@ impl<const N: usize> TwoLeveltreeSolution<N> {
 
         // \sqrt{N} 個くらいの segment に分割して登録
         let no_segment = (len as f64).sqrt().ceil() as usize;
-        let segment_capacity = std::u16::MAX.min(10 * no_segment as u16);
+        let segment_capacity = (u16::MAX as usize).min(10 * no_segment) as u16;
         let mut segment_list = SegmentIDList::new(segment_capacity);
         let mut buffer = vec![Segment::<N>::new(); segment_capacity as usize];
 
@@ -266,11 +270,11 @@ impl<const N: usize> TwoLeveltreeSolution<N> {
                 buffer[segment_id as usize].push(node);
                 index_of[node as usize].inner_id = inner_id;
                 index_of[node as usize].segment_id = segment_id;
-                node = sol.next(node as u32);
+                node = sol.next(node);
             }
             buffer[segment_id as usize].start_1d_index = accumulated_segment_size;
             accumulated_segment_size += segment_size;
-            segment_list.push(segment_id as u16);
+            segment_list.push(segment_id);
         }
 
         TwoLeveltreeSolution {
@@ -429,6 +433,7 @@ impl<const N: usize> TwoLeveltreeSolution<N> {
         (segment_index, exact_inner_index)
     }
 
+    #[allow(dead_code)]
     fn validate(&self) {
         self.segment_list.validate();
     }
@@ -605,7 +610,7 @@ impl<const N: usize> Solution for TwoLeveltreeSolution<N> {
     fn id_of(&self, index: usize) -> u32 {
         // どの segment に属しているかを二分探索
         // start_index <= index を満たす buffer が知りたい
-        let mut left = 0 as usize;
+        let mut left = 0;
         let mut right = self.segment_list.len();
         while right - left >= 2 {
             let middle = (right + left) / 2;
@@ -618,8 +623,13 @@ impl<const N: usize> Solution for TwoLeveltreeSolution<N> {
         }
         let segment_id = self.segment_list.content[left];
         let inner_index = index - self.buffer[segment_id as usize].start_1d_index;
-        assert!(inner_index < self.buffer[segment_id as usize].len());
-        self.buffer[segment_id as usize].array[inner_index]
+        let segment_size = self.buffer[segment_id as usize].len();
+        assert!(inner_index < segment_size);
+        if self.buffer[segment_id as usize].reversed {
+            self.buffer[segment_id as usize].array[segment_size - 1 - inner_index]
+        } else {
+            self.buffer[segment_id as usize].array[inner_index]
+        }
     }
 }
 
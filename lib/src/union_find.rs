@@ -0,0 +1,34 @@
+pub struct UnionFind {
+    parent: Vec<u32>,
+}
+
+impl UnionFind {
+    pub fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parent: (0..n as u32).collect(),
+        }
+    }
+
+    pub fn find(&mut self, id: u32) -> u32 {
+        let mut root = id;
+        while self.parent[root as usize] != root {
+            root = self.parent[root as usize];
+        }
+        // 経路圧縮
+        let mut id = id;
+        while self.parent[id as usize] != root {
+            let next = self.parent[id as usize];
+            self.parent[id as usize] = root;
+            id = next;
+        }
+        root
+    }
+
+    pub fn unite(&mut self, id1: u32, id2: u32) {
+        let root1 = self.find(id1);
+        let root2 = self.find(id2);
+        if root1 != root2 {
+            self.parent[root1 as usize] = root2;
+        }
+    }
+}